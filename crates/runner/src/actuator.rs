@@ -0,0 +1,249 @@
+use crate::pendulum::{Pendulum, CART_ACC};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Permanent magnet DC motor driving the cart through a belt.
+#[derive(Clone, Debug)]
+pub struct DcMotor {
+    /// Supply voltage applied at full command.
+    pub max_voltage: f32,
+    /// Winding resistance in ohms.
+    pub resistance: f32,
+    /// Force per ampere at the belt.
+    pub force_constant: f32,
+    /// Back-EMF in volts per metre per second of cart velocity.
+    pub back_emf: f32,
+    /// Mass of the cart and everything it drags along.
+    pub mass: f32,
+}
+
+impl DcMotor {
+    fn acceleration(&self, command: f32, cart_linvel: f32) -> f32 {
+        let voltage = command * self.max_voltage;
+        let current = (voltage - self.back_emf * cart_linvel) / self.resistance;
+        self.force_constant * current / self.mass
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Motor {
+    /// The commanded fraction of the maximum acceleration is applied directly.
+    Ideal,
+    Dc(DcMotor),
+}
+
+/// Drive model between a policy's command and the force on the cart.
+///
+/// The default configuration applies commands instantly, like the original bang-bang control.
+#[derive(Clone, Debug)]
+pub struct ActuatorConfig {
+    pub motor: Motor,
+    /// Turns requested speeds into full forward, full reverse or off, like the original control,
+    /// instead of passing them on as a fraction of the full command.
+    pub bang_bang: bool,
+    /// Commands with a smaller magnitude don't move the motor.
+    pub dead_zone: f32,
+    /// Largest change of the command per second.
+    pub rate_limit: f32,
+    /// Number of ticks between a command being issued and reaching the motor.
+    pub delay_ticks: usize,
+    /// Saturation of the resulting cart acceleration.
+    pub max_acc: f32,
+}
+
+impl Default for ActuatorConfig {
+    fn default() -> Self {
+        Self {
+            motor: Motor::Ideal,
+            bang_bang: true,
+            dead_zone: 0.0,
+            rate_limit: f32::INFINITY,
+            delay_ticks: 0,
+            max_acc: CART_ACC,
+        }
+    }
+}
+
+impl ActuatorConfig {
+    /// A small hobby motor on a 12 V supply with a bit of driver lag.
+    pub fn realistic() -> Self {
+        Self {
+            motor: Motor::Dc(DcMotor {
+                max_voltage: 12.0,
+                resistance: 2.0,
+                force_constant: 0.5,
+                back_emf: 12.0,
+                mass: 0.75,
+            }),
+            bang_bang: false,
+            dead_zone: 0.05,
+            rate_limit: 20.0,
+            delay_ticks: 1,
+            max_acc: 2.0 * CART_ACC,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Actuator {
+    config: ActuatorConfig,
+    queue: VecDeque<f32>,
+    command: f32,
+    acceleration: f32,
+}
+
+impl Actuator {
+    pub fn new(config: ActuatorConfig) -> Self {
+        Self {
+            queue: VecDeque::from(vec![0.0; config.delay_ticks]),
+            config,
+            command: 0.0,
+            acceleration: 0.0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new(self.config.clone());
+    }
+
+    /// Issues the command for a policy's requested `speed`, sets the resulting cart acceleration
+    /// and returns the command, in `-1.0..=1.0`.
+    pub fn drive(&mut self, pendulum: &mut Pendulum, speed: f32, delta: Duration) -> f32 {
+        let config = &self.config;
+        let command = if config.bang_bang {
+            bang_bang(speed)
+        } else {
+            speed.clamp(-1.0, 1.0)
+        };
+        self.queue.push_back(command);
+        let target = self.queue.pop_front().unwrap();
+        let target = if target.abs() < config.dead_zone {
+            0.0
+        } else {
+            target
+        };
+
+        self.command = if config.rate_limit.is_finite() {
+            let max_step = config.rate_limit * delta.as_secs_f32();
+            self.command + (target - self.command).clamp(-max_step, max_step)
+        } else {
+            target
+        };
+
+        let acceleration = match &config.motor {
+            Motor::Ideal => self.command * CART_ACC,
+            Motor::Dc(motor) => motor.acceleration(self.command, pendulum.cart_linvel()),
+        };
+        self.acceleration = acceleration.clamp(-config.max_acc, config.max_acc);
+        pendulum.accelerate(self.acceleration);
+        command
+    }
+
+    /// Cart acceleration set by the last call to `drive`.
+    pub fn acceleration(&self) -> f32 {
+        self.acceleration
+    }
+}
+
+/// Bang-bang command for the requested speed.
+pub fn bang_bang(speed: f32) -> f32 {
+    if speed > 0.1 {
+        1.0
+    } else if speed < -0.1 {
+        -1.0
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICK: Duration = Duration::from_millis(50);
+
+    fn proportional() -> ActuatorConfig {
+        ActuatorConfig {
+            bang_bang: false,
+            ..ActuatorConfig::default()
+        }
+    }
+
+    /// Accelerations after driving a cart at rest with each of `speeds` in turn.
+    fn accelerations(config: ActuatorConfig, speeds: &[f32]) -> Vec<f32> {
+        let mut actuator = Actuator::new(config);
+        let mut pendulum = Pendulum::new();
+        speeds
+            .iter()
+            .map(|&speed| {
+                actuator.drive(&mut pendulum, speed, TICK);
+                actuator.acceleration()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn delay_holds_commands_back_for_whole_ticks() {
+        let config = ActuatorConfig {
+            delay_ticks: 2,
+            ..ActuatorConfig::default()
+        };
+        assert_eq!(
+            accelerations(config, &[1.0, -1.0, 0.0, 0.0]),
+            [0.0, 0.0, CART_ACC, -CART_ACC]
+        );
+    }
+
+    #[test]
+    fn rate_limit_ramps_the_command() {
+        let config = ActuatorConfig {
+            rate_limit: 10.0,
+            ..ActuatorConfig::default()
+        };
+        assert_eq!(
+            accelerations(config, &[1.0, 1.0, 1.0]),
+            [0.5 * CART_ACC, CART_ACC, CART_ACC]
+        );
+    }
+
+    #[test]
+    fn dead_zone_ignores_small_commands() {
+        let config = ActuatorConfig {
+            dead_zone: 0.1,
+            ..proportional()
+        };
+        assert_eq!(
+            accelerations(config, &[0.05, -0.05, 0.5]),
+            [0.0, 0.0, 0.5 * CART_ACC]
+        );
+    }
+
+    #[test]
+    fn back_emf_and_saturation_limit_the_motor() {
+        let motor = DcMotor {
+            max_voltage: 12.0,
+            resistance: 2.0,
+            force_constant: 0.5,
+            back_emf: 12.0,
+            mass: 0.75,
+        };
+        let config = ActuatorConfig {
+            motor: Motor::Dc(motor),
+            max_acc: 3.0,
+            ..proportional()
+        };
+        let mut actuator = Actuator::new(config);
+        let mut pendulum = Pendulum::new();
+        // 4 m/s² at rest, saturated.
+        actuator.drive(&mut pendulum, 1.0, TICK);
+        assert_eq!(actuator.acceleration(), 3.0);
+        // The back-EMF at 0.5 m/s cancels half the supply voltage.
+        pendulum.set_cart_linvel(0.5);
+        actuator.drive(&mut pendulum, 1.0, TICK);
+        assert!((actuator.acceleration() - 2.0).abs() < 1e-5);
+        // At 1 m/s it cancels all of it.
+        pendulum.set_cart_linvel(1.0);
+        actuator.drive(&mut pendulum, 1.0, TICK);
+        assert!(actuator.acceleration().abs() < 1e-5);
+    }
+}
@@ -0,0 +1,324 @@
+use crate::dashboard::Dashboard;
+use crate::ml::cmaes::CmaesConfig;
+use crate::ml::curriculum::CurriculumConfig;
+use crate::ml::hall_of_fame::{self, HallOfFame};
+use crate::ml::islands::IslandConfig;
+use crate::ml::metrics::MetricsLog;
+use crate::ml::pendulum::EnvConfig;
+use crate::ml::pendulum::{self, PendulumAgent};
+use crate::ml::qlearning::QConfig;
+use crate::ml::rl::RlConfig;
+use crate::ml::selection::SelectionConfig;
+use crate::ml::{Ml, Search};
+use crate::recording::Recording;
+use crate::trajectory::Trajectory;
+use std::path::Path;
+use std::str::FromStr;
+
+fn flag(args: &[String], name: &str) -> bool {
+    args.iter().any(|a| a == name)
+}
+
+fn option<T: FromStr>(args: &[String], name: &str) -> Option<T> {
+    let value = args.iter().position(|a| a == name).map(|i| {
+        args.get(i + 1)
+            .unwrap_or_else(|| fail(&format!("{name} needs a value")))
+    })?;
+    Some(
+        value
+            .parse()
+            .unwrap_or_else(|_| fail(&format!("Invalid value for {name}: {value}"))),
+    )
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    std::process::exit(2);
+}
+
+/// `runner replay <recording>...`: checks that every recording still reproduces exactly.
+pub fn replay(args: &[String]) {
+    if args.is_empty() {
+        fail("Usage: runner replay <recording>...");
+    }
+    let mut failed = false;
+    for path in args {
+        match Recording::load(Path::new(path)) {
+            Ok(recording) => match recording.verify() {
+                Ok(()) => println!("{path}: ok ({} ticks)", recording.ticks()),
+                Err(divergence) => {
+                    failed = true;
+                    println!(
+                        "{path}: diverged at tick {}\n  expected {:?}\n  actual   {:?}",
+                        divergence.tick, divergence.expected, divergence.actual
+                    );
+                }
+            },
+            Err(err) => {
+                failed = true;
+                println!("{path}: {err}");
+            }
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// `runner train [--generations N] [--search SEARCH] [--selection SELECTION] [--elites N]
+/// [--squash FUNCTION] [--islands N] [--migration-interval N] [--migrants N]
+/// [--success-upright F] [--promotion-rate F] [--patience N] [--log FILE] [--trajectories DIR]
+/// [--champions DIR] [--hall-of-fame DIR] [--tui]`: evolves agents without opening a window.
+/// `SEARCH` is one of `fitness`, `novelty`, `map-elites`, `nsga2`, `islands` or `curriculum`,
+/// `SELECTION` one of `proportional`, `tournament[:size]`, `rank`, `truncation[:fraction]`,
+/// `boltzmann[:temperature]` or `sus`, and `FUNCTION` the activation applied to the outputs of
+/// new agents. The island options only apply to `--search islands`, and the success, promotion
+/// and patience ones to `--search curriculum`.
+pub fn train(args: &[String]) {
+    let generations = option(args, "--generations");
+    let search = option(args, "--search").unwrap_or_default();
+    let defaults = SelectionConfig::default();
+    let selection = SelectionConfig {
+        selection: option(args, "--selection").unwrap_or(defaults.selection),
+        elites: option(args, "--elites").unwrap_or(defaults.elites),
+    };
+    let squash = option(args, "--squash");
+    let defaults = IslandConfig::default();
+    let islands = IslandConfig {
+        islands: option(args, "--islands").unwrap_or(defaults.islands),
+        migration_interval: option(args, "--migration-interval")
+            .unwrap_or(defaults.migration_interval),
+        migrants: option(args, "--migrants").unwrap_or(defaults.migrants),
+        ..defaults
+    };
+    if islands.islands == 0 {
+        fail("--islands must be at least 1");
+    }
+    let defaults = CurriculumConfig::default();
+    let curriculum = CurriculumConfig {
+        success_upright: option(args, "--success-upright").unwrap_or(defaults.success_upright),
+        promotion_rate: option(args, "--promotion-rate").unwrap_or(defaults.promotion_rate),
+        patience: option(args, "--patience").unwrap_or(defaults.patience),
+        ..defaults
+    };
+    run_training(args, move |ml| {
+        ml.select_with(selection);
+        if let Some(squash) = squash {
+            ml.squash_outputs_with(squash);
+        }
+        match search {
+            Search::Islands => ml.run_islands(&islands, generations),
+            Search::Curriculum => ml.run_curriculum(&curriculum, generations),
+            _ => ml.run_search(search, generations),
+        }
+    });
+}
+
+/// `runner cmaes [--agent FILE | --hidden N] [--evaluations N] [--restart none|ipop|bipop]
+/// [--sigma S] [--population N]` plus the reporting options of `train`: tunes the weights of a
+/// fixed topology, by default a linear policy.
+pub fn cmaes(args: &[String]) {
+    let topology = match option::<String>(args, "--agent") {
+        Some(path) => PendulumAgent::load(Path::new(&path))
+            .unwrap_or_else(|err| fail(&format!("Failed to load {path}: {err}"))),
+        None => PendulumAgent::layered(option(args, "--hidden").unwrap_or(0)),
+    };
+    let population = option(args, "--population");
+    if population.is_some_and(|population: usize| population < 2) {
+        fail("--population must be at least 2");
+    }
+    let defaults = CmaesConfig::default();
+    let config = CmaesConfig {
+        sigma: option(args, "--sigma").unwrap_or(defaults.sigma),
+        population,
+        restart: option(args, "--restart").unwrap_or(defaults.restart),
+        max_evaluations: option(args, "--evaluations").unwrap_or(defaults.max_evaluations),
+    };
+    run_training(args, move |ml| ml.run_cmaes(topology, &config));
+}
+
+/// `runner rl [--algorithm ppo|reinforce] [--iterations N] [--episodes N] [--hidden N]
+/// [--learning-rate R] [--log FILE] [--tui]`: learns a stochastic policy with gradients instead
+/// of evolution.
+pub fn rl(args: &[String]) {
+    reject_agent_options(args);
+    let defaults = RlConfig::default();
+    let config = RlConfig {
+        algorithm: option(args, "--algorithm").unwrap_or(defaults.algorithm),
+        hidden: option(args, "--hidden").unwrap_or(defaults.hidden),
+        episodes: option(args, "--episodes").unwrap_or(defaults.episodes),
+        learning_rate: option(args, "--learning-rate").unwrap_or(defaults.learning_rate),
+        iterations: option(args, "--iterations"),
+        ..defaults
+    };
+    if config.episodes == 0 {
+        fail("--episodes must be at least 1");
+    }
+    run_training(args, move |ml| ml.run_rl(&config));
+}
+
+/// `runner q-learning [--method q-learning|sarsa] [--bins N] [--tilings N] [--iterations N]
+/// [--episodes N] [--learning-rate R] [--log FILE] [--tui]`: learns action values for the
+/// bang-bang commands, as a table with `--tilings 1`.
+pub fn q_learning(args: &[String]) {
+    reject_agent_options(args);
+    let defaults = QConfig::default();
+    let config = QConfig {
+        method: option(args, "--method").unwrap_or(defaults.method),
+        bins: option(args, "--bins").unwrap_or(defaults.bins),
+        tilings: option(args, "--tilings").unwrap_or(defaults.tilings),
+        learning_rate: option(args, "--learning-rate").unwrap_or(defaults.learning_rate),
+        episodes: option(args, "--episodes").unwrap_or(defaults.episodes),
+        iterations: option(args, "--iterations"),
+        ..defaults
+    };
+    if config.episodes == 0 {
+        fail("--episodes must be at least 1");
+    }
+    run_training(args, move |ml| ml.run_q_learning(&config));
+}
+
+/// Policies learned by `rl` and `q-learning` are not agents, so there are no champions to
+/// export, save or enter into the hall of fame.
+fn reject_agent_options(args: &[String]) {
+    for name in ["--trajectories", "--champions", "--hall-of-fame"] {
+        if flag(args, name) {
+            fail(&format!("{name} needs a command that trains agents"));
+        }
+    }
+}
+
+/// Sets up the reporting shared by the training commands and runs `train`, next to the terminal
+/// dashboard if `--tui` is given.
+fn run_training(args: &[String], train: impl FnOnce(&mut Ml) + Send + 'static) {
+    let (tx, agents) = std::sync::mpsc::channel();
+    // Nobody watches the champions here, but training stops once they can't be sent anymore.
+    std::thread::spawn(move || agents.iter().for_each(drop));
+    let mut ml = Ml::new(tx, EnvConfig::from_env());
+    if let Some(path) = option::<String>(args, "--log") {
+        let log = MetricsLog::create(Path::new(&path))
+            .unwrap_or_else(|err| fail(&format!("Failed to create {path}: {err}")));
+        ml.log_metrics(log);
+    }
+    if let Some(dir) = option::<String>(args, "--trajectories") {
+        ml.export_trajectories(dir.into());
+    }
+    if let Some(dir) = option::<String>(args, "--champions") {
+        ml.save_champions(dir.into());
+    }
+    if let Some(dir) = option::<String>(args, "--hall-of-fame") {
+        let hall = HallOfFame::open(dir.clone().into())
+            .unwrap_or_else(|err| fail(&format!("Failed to open {dir}: {err}")));
+        let name = hall_of_fame::run_name();
+        println!("Entering the hall of fame as {name}");
+        ml.enter_hall_of_fame(hall, name);
+    }
+
+    if flag(args, "--tui") {
+        let (stats_tx, stats_rx) = std::sync::mpsc::channel();
+        ml.report_stats(stats_tx);
+        let trainer = std::thread::spawn(move || train(&mut ml));
+        Dashboard::new().run(stats_rx);
+        trainer.join().unwrap();
+    } else {
+        train(&mut ml);
+    }
+}
+
+/// `runner tournament [DIR | AGENT...] [--scenarios N] [--seed S]`: evaluates every agent of
+/// the hall of fame in `DIR`, `hall-of-fame` by default, or the given agent files on the same
+/// scenarios, and prints a leaderboard by mean score.
+pub fn tournament(args: &[String]) {
+    let paths: Vec<&String> = args.iter().take_while(|a| !a.starts_with("--")).collect();
+    let entries = match paths.as_slice() {
+        [] => hall_of_fame::load_all(Path::new("hall-of-fame")),
+        [dir] if Path::new(dir).is_dir() => hall_of_fame::load_all(Path::new(dir)),
+        files => files
+            .iter()
+            .map(|path| hall_of_fame::load_entry(Path::new(path)))
+            .collect(),
+    }
+    .unwrap_or_else(|err| fail(&format!("Failed to load agents: {err}")));
+    if entries.is_empty() {
+        fail("No agents to compare");
+    }
+
+    let scenarios = hall_of_fame::tournament_scenarios(
+        option(args, "--scenarios").unwrap_or(10),
+        option(args, "--seed").unwrap_or(0),
+    );
+    let standings = hall_of_fame::tournament(entries, &scenarios, &EnvConfig::from_env());
+    let width = standings.iter().map(|s| s.name.len()).max().unwrap().max(4);
+    println!(
+        "{:>4}  {:<width$}  {:>9}  {:>9}  {:>9}",
+        "rank", "name", "mean", "standard", "worst"
+    );
+    for (rank, s) in standings.iter().enumerate() {
+        println!(
+            "{:>4}  {:<width$}  {:>9.1}  {:>9.1}  {:>9.1}",
+            rank + 1,
+            s.name,
+            s.mean,
+            s.standard,
+            s.worst
+        );
+    }
+}
+
+/// `runner graph <agent> [--svg FILE]`: prints the network of a saved agent as a Graphviz
+/// document, and optionally draws it as SVG.
+pub fn graph(args: &[String]) {
+    let Some(path) = args.first().filter(|a| !a.starts_with("--")) else {
+        fail("Usage: runner graph <agent> [--svg FILE]");
+    };
+    let agent = PendulumAgent::load(Path::new(path))
+        .unwrap_or_else(|err| fail(&format!("Failed to load {path}: {err}")));
+    let graph = agent.graph();
+    print!("{}", graph.to_dot());
+    if let Some(svg) = option::<String>(args, "--svg") {
+        std::fs::write(&svg, graph.to_svg())
+            .unwrap_or_else(|err| fail(&format!("Failed to write {svg}: {err}")));
+    }
+}
+
+/// `runner prune <agent> <output> [--min-weight W] [--verify]`: removes dead nodes and
+/// negligible edges. With `--verify`, the result is only saved if it commands the cart exactly
+/// like the original along the original's own simulated trajectory.
+pub fn prune(args: &[String]) {
+    let [input, output, ..] = args else {
+        fail("Usage: runner prune <agent> <output> [--min-weight W] [--verify]");
+    };
+    let mut agent = PendulumAgent::load(Path::new(input))
+        .unwrap_or_else(|err| fail(&format!("Failed to load {input}: {err}")));
+    let mut pruned = agent.clone();
+    let report = pruned.prune(option(args, "--min-weight").unwrap_or(0.0));
+    println!(
+        "Removed {} nodes and {} edges, {} nodes and {} edges left",
+        report.nodes_removed,
+        report.edges_removed,
+        pruned.node_count(),
+        pruned.edge_count()
+    );
+
+    if flag(args, "--verify") {
+        let mut trajectory = Trajectory::new();
+        pendulum::run_simulation_traced(
+            &mut agent.clone(),
+            &EnvConfig::from_env(),
+            &mut trajectory,
+        );
+        let inputs = pendulum::inputs_along(&trajectory);
+        println!(
+            "Largest output difference over {} steps: {}",
+            inputs.len(),
+            agent.max_output_difference(&mut pruned, &inputs)
+        );
+        if let Some(step) = pendulum::first_divergence(&mut agent, &mut pruned, &trajectory) {
+            println!("Commands differ at step {step}, not saving");
+            std::process::exit(1);
+        }
+    }
+    pruned
+        .save(Path::new(output))
+        .unwrap_or_else(|err| fail(&format!("Failed to save {output}: {err}")));
+}
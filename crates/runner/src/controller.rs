@@ -1,10 +1,26 @@
 use crate::{
-    ml::pendulum::{set_pendulum_inputs, PendulumAgent},
-    pendulum::Pendulum,
+    ml::{
+        graph::NodeKind,
+        hall_of_fame::{self, HallOfFame},
+        nsga2::ParetoFront,
+        pendulum::{set_pendulum_inputs, step_reward, EnvConfig, Hardware},
+        selection::{Selection, SelectionConfig},
+        training::{Command, Snapshot, Training},
+        Champion, Search,
+    },
+    pendulum::{Pendulum, PendulumState},
+    recording::Recording,
+    trajectory::Trajectory,
+};
+use bytemuck::Zeroable;
+use glam::*;
+use shared::{
+    NetEdge, NetNode, NetworkView, ShaderConstants, MAX_NET_EDGES, MAX_NET_NODES, NET_NODE_HIDDEN,
+    NET_NODE_INPUT, NET_NODE_OUTPUT,
 };
-use shared::ShaderConstants;
 use std::{
-    sync::mpsc::Receiver,
+    collections::VecDeque,
+    sync::mpsc::{Receiver, TryRecvError},
     time::{Duration, Instant},
 };
 use winit::{
@@ -13,34 +29,135 @@ use winit::{
     keyboard::{Key, NamedKey},
 };
 
+const BOB_GRAB_RADIUS: f32 = 0.06;
+const CART_GRAB_HALF_SIZE: Vec2 = vec2(0.14, 0.06);
+const DRAG_STIFFNESS: f32 = 60.0;
+const DRAG_DAMPING: f32 = 8.0;
+const SAVE_SLOTS: usize = 9;
+const REWIND_CAPACITY: usize = 60 * 20;
+/// Parent selections the `o` key cycles through.
+const SELECTIONS: [Selection; 6] = [
+    Selection::Proportional,
+    Selection::Tournament { size: 3 },
+    Selection::Rank,
+    Selection::Truncation { fraction: 0.5 },
+    Selection::Boltzmann { temperature: 100.0 },
+    Selection::StochasticUniversal,
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Grab {
+    Bob,
+    Cart,
+}
+
+/// Everything needed to resume the simulation from a given moment.
+#[derive(Clone)]
+struct SaveState {
+    time: Duration,
+    pendulum: PendulumState,
+    hardware: Hardware,
+    champion: Champion,
+    /// Whether `champion` was pinned, or else the latest agent from training.
+    pinned: bool,
+    /// Agents received from training by then, which tells whether the latest is still the same.
+    agent_count: usize,
+    episode_score: f32,
+}
+
 pub struct Controller {
     prev_instant: Instant,
+    /// Simulated time since the last reset.
+    time: Duration,
+    /// Score accumulated since the last reset, as `run_simulation` would count it.
+    episode_score: f32,
     current_direction: Option<NamedKey>,
     mouse_button_pressed: u32,
     cursor_x: f32,
     cursor_y: f32,
+    window_size: PhysicalSize<u32>,
+    grab: Option<Grab>,
     pendulum: Pendulum,
-    rx: Receiver<PendulumAgent>,
-    agents: Vec<PendulumAgent>,
+    hardware: Hardware,
+    rx: Receiver<Champion>,
+    agents: Vec<Champion>,
+    /// Agent restored from a save state or picked from the front, used instead of the latest one
+    /// from training.
+    pinned_agent: Option<Champion>,
+    front_rx: Receiver<ParetoFront>,
+    /// Latest Pareto front of multi-objective training, empty for the other searches.
+    front: ParetoFront,
+    front_index: usize,
+    /// Stops training when the viewer goes away.
+    training: Training,
+    training_paused: bool,
+    /// Answer to the last `s`, printed when it arrives.
+    pending_snapshot: Option<Receiver<Snapshot>>,
+    search: Search,
+    selection_index: usize,
+    save_slots: [Option<SaveState>; SAVE_SLOTS],
+    history: VecDeque<SaveState>,
+    rewinding: bool,
+    recording: Option<Recording>,
+    trajectory: Option<Trajectory>,
 }
 
 impl Controller {
-    pub fn new() -> Self {
+    pub fn new(window_size: PhysicalSize<u32>) -> Self {
+        let env = EnvConfig::from_env();
         let (tx, rx) = std::sync::mpsc::channel();
-        let mut ml0 = crate::ml::Ml::new(tx);
-        std::thread::spawn(move || {
-            ml0.run_experiment();
-        });
+        let mut ml0 = crate::ml::Ml::new(tx, env.clone());
+        if let Some(dir) = std::env::var_os("PENDULUM_TRAJECTORIES") {
+            ml0.export_trajectories(dir.into());
+        }
+        if let Some(dir) = std::env::var_os("PENDULUM_CHAMPIONS") {
+            ml0.save_champions(dir.into());
+        }
+        if let Some(dir) = std::env::var_os("PENDULUM_HALL_OF_FAME") {
+            match HallOfFame::open(dir.into()) {
+                Ok(hall) => ml0.enter_hall_of_fame(hall, hall_of_fame::run_name()),
+                Err(err) => eprintln!("Failed to open the hall of fame: {err}"),
+            }
+        }
+        let search: Search = match std::env::var("PENDULUM_SEARCH") {
+            Ok(name) => name.parse().unwrap_or_else(|err| {
+                eprintln!("Unknown PENDULUM_SEARCH: {err}, using `fitness`");
+                Search::default()
+            }),
+            Err(_) => Search::default(),
+        };
+        let (front_tx, front_rx) = std::sync::mpsc::channel();
+        ml0.report_front(front_tx);
+        let training = Training::spawn(ml0, move |ml| ml.run_search(search, None));
         let agent = rx.recv().unwrap();
         Self {
             prev_instant: Instant::now(),
+            time: Duration::ZERO,
+            episode_score: 0.0,
             current_direction: None,
             mouse_button_pressed: 0,
             cursor_x: 0.0,
             cursor_y: 0.0,
+            window_size,
+            grab: None,
             pendulum: Pendulum::new(),
+            hardware: Hardware::new(&env),
             rx,
             agents: vec![agent],
+            pinned_agent: None,
+            front_rx,
+            front: Vec::new(),
+            front_index: 0,
+            training,
+            training_paused: false,
+            pending_snapshot: None,
+            search,
+            selection_index: 0,
+            save_slots: Default::default(),
+            history: VecDeque::with_capacity(REWIND_CAPACITY),
+            rewinding: false,
+            recording: None,
+            trajectory: None,
         }
     }
 
@@ -50,6 +167,16 @@ impl Controller {
             ElementState::Pressed => self.mouse_button_pressed |= mask,
             ElementState::Released => self.mouse_button_pressed &= !mask,
         }
+        if button == MouseButton::Left {
+            self.grab = match state {
+                ElementState::Pressed => self.grab_at(self.cursor_world_pos()),
+                ElementState::Released => None,
+            };
+        }
+    }
+
+    pub fn on_resize(&mut self, size: PhysicalSize<u32>) {
+        self.window_size = size;
     }
 
     pub fn on_mouse_move(&mut self, position: PhysicalPosition<f64>) {
@@ -81,41 +208,416 @@ impl Controller {
                     self.pendulum.stop();
                 }
             }
-            Key::Character(str) if str == "r" => self.pendulum.reset(),
+            Key::Character(str) if str == "r" => {
+                self.pendulum.reset();
+                self.hardware.reset();
+                self.active_champion_mut().agent.reset_state();
+                self.time = Duration::ZERO;
+                self.episode_score = 0.0;
+                self.record_restore();
+            }
+            Key::Character(str) if str == "u" => {
+                let upright = PendulumState::upright();
+                self.pendulum.restore(PendulumState {
+                    bob_angle: upright.bob_angle,
+                    bob_angvel: upright.bob_angvel,
+                    ..self.pendulum.snapshot()
+                });
+                self.record_restore();
+            }
+            Key::Character(str) if str == "c" && state.is_pressed() => self.toggle_recording(),
+            Key::Character(str) if str == "t" && state.is_pressed() => self.toggle_trajectory(),
+            Key::Character(str) if str == "l" => self.pin(None),
+            Key::Character(str) if str == "[" && state.is_pressed() => self.step_through_front(-1),
+            Key::Character(str) if str == "]" && state.is_pressed() => self.step_through_front(1),
+            Key::Character(str) if str == "p" && state.is_pressed() => self.toggle_training(),
+            Key::Character(str) if str == "o" && state.is_pressed() => self.next_selection(),
+            Key::Character(str) if str == "s" && state.is_pressed() => self.request_snapshot(),
+            Key::Character(str) if str == "x" && state.is_pressed() => {
+                self.training.send(Command::Stop);
+                println!("Training stopped");
+            }
+            Key::Character(str) if state.is_pressed() && save_slot(&str).is_some() => {
+                if let Some(save) = self.save_slots[save_slot(&str).unwrap()].clone() {
+                    self.load(save, true);
+                }
+            }
+            Key::Named(key) if state.is_pressed() && function_key_slot(key).is_some() => {
+                self.save_slots[function_key_slot(key).unwrap()] = Some(self.save());
+            }
+            Key::Named(NamedKey::Backspace) => self.rewinding = state.is_pressed(),
             _ => {}
         }
     }
 
+    fn save(&self) -> SaveState {
+        SaveState {
+            time: self.time,
+            pendulum: self.pendulum.snapshot(),
+            hardware: self.hardware.clone(),
+            champion: self.active_champion().clone(),
+            pinned: self.pinned_agent.is_some(),
+            agent_count: self.agents.len(),
+            episode_score: self.episode_score,
+        }
+    }
+
+    /// Restores `save`, pinning its agent if `pin` is set or it was pinned when saved. Otherwise
+    /// the latest agent from training stays in control, with the state it had then if it is
+    /// still the same agent.
+    fn load(&mut self, save: SaveState, pin: bool) {
+        self.time = save.time;
+        self.pendulum.restore(save.pendulum);
+        self.hardware = save.hardware;
+        if pin || save.pinned {
+            self.pinned_agent = Some(save.champion);
+        } else {
+            self.pinned_agent = None;
+            if self.agents.len() == save.agent_count {
+                *self.agents.last_mut().unwrap() = save.champion;
+            } else {
+                // The latest agent never saw the restored pendulum.
+                self.active_champion_mut().agent.reset_state();
+            }
+        }
+        self.episode_score = save.episode_score;
+        self.record_restore();
+    }
+
+    /// Pins the member of the Pareto front `step` places away from the last one picked, from the
+    /// most to the least time upright and wrapping around.
+    fn step_through_front(&mut self, step: isize) {
+        if self.front.is_empty() {
+            return;
+        }
+        let len = self.front.len() as isize;
+        self.front_index = (self.front_index as isize + step).rem_euclid(len) as usize;
+        let (champion, objectives) = &self.front[self.front_index];
+        println!(
+            "Front {}/{}: upright {:.3}, smoothness {:.3}, centring {:.3}, score {}",
+            self.front_index + 1,
+            len,
+            objectives.upright,
+            objectives.smoothness,
+            objectives.centring,
+            champion.score
+        );
+        self.pin(Some(champion.clone()));
+    }
+
+    /// Hands control to `champion`, or back to the latest agent from training, from a fresh
+    /// state.
+    fn pin(&mut self, champion: Option<Champion>) {
+        self.pinned_agent = champion;
+        self.active_champion_mut().agent.reset_state();
+    }
+
+    fn toggle_training(&mut self) {
+        if self.training.is_finished() {
+            println!("Training has ended");
+            return;
+        }
+        self.training_paused = !self.training_paused;
+        if self.training_paused {
+            self.training.send(Command::Pause);
+            println!("Training paused");
+        } else {
+            self.training.send(Command::Resume);
+            println!("Training resumed");
+        }
+    }
+
+    /// Switches training to the next of `SELECTIONS`, keeping the default elites, if the search
+    /// follows it.
+    fn next_selection(&mut self) {
+        if !self.search.follows_selection() {
+            println!(
+                "{:?} search doesn't support changing the selection",
+                self.search
+            );
+            return;
+        }
+        self.selection_index = (self.selection_index + 1) % SELECTIONS.len();
+        let selection = SELECTIONS[self.selection_index];
+        println!("Selecting parents with {selection:?}");
+        self.training.send(Command::Select(SelectionConfig {
+            selection,
+            ..SelectionConfig::default()
+        }));
+    }
+
+    /// Asks training where it stands, for `poll_snapshot` to print within a generation.
+    fn request_snapshot(&mut self) {
+        if self.training.is_finished() {
+            println!("Training has ended");
+        } else if self.pending_snapshot.is_none() {
+            self.pending_snapshot = Some(self.training.request_snapshot());
+        }
+    }
+
+    fn poll_snapshot(&mut self) {
+        let Some(rx) = &self.pending_snapshot else {
+            return;
+        };
+        let snapshot = match rx.try_recv() {
+            Ok(snapshot) => snapshot,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => {
+                self.pending_snapshot = None;
+                println!("Training has ended");
+                return;
+            }
+        };
+        self.pending_snapshot = None;
+        println!(
+            "Training {} at generation {}, best score {}",
+            if snapshot.paused { "paused" } else { "running" },
+            snapshot.generation,
+            snapshot.best.map_or(0.0, |best| best.score)
+        );
+    }
+
+    fn toggle_recording(&mut self) {
+        match self.recording.take() {
+            None => self.recording = Some(Recording::new(self.pendulum.snapshot())),
+            Some(recording) => {
+                let path = std::path::PathBuf::from(format!("recordings/{}.rec", unix_secs()));
+                match recording.save(&path) {
+                    Ok(()) => println!("Saved {} ticks to {}", recording.ticks(), path.display()),
+                    Err(err) => eprintln!("Failed to save {}: {err}", path.display()),
+                }
+            }
+        }
+    }
+
+    fn toggle_trajectory(&mut self) {
+        match self.trajectory.take() {
+            None => self.trajectory = Some(Trajectory::new()),
+            Some(trajectory) if trajectory.is_empty() => {}
+            Some(trajectory) => {
+                let stem = std::path::PathBuf::from(format!("trajectories/{}", unix_secs()));
+                match trajectory.export(&stem) {
+                    Ok(()) => println!("Saved {} steps to {}", trajectory.len(), stem.display()),
+                    Err(err) => eprintln!("Failed to save {}: {err}", stem.display()),
+                }
+            }
+        }
+    }
+
+    fn record_restore(&mut self) {
+        if let Some(recording) = &mut self.recording {
+            recording.restore(self.pendulum.snapshot());
+        }
+    }
+
+    fn active_champion(&self) -> &Champion {
+        self.pinned_agent
+            .as_ref()
+            .unwrap_or_else(|| self.agents.last().unwrap())
+    }
+
+    fn active_champion_mut(&mut self) -> &mut Champion {
+        match &mut self.pinned_agent {
+            Some(champion) => champion,
+            None => self.agents.last_mut().unwrap(),
+        }
+    }
+
     pub fn update(&mut self) {
         let max_duration = Duration::from_secs_f64(1.0 / 30.0);
         let now = Instant::now();
         let duration = (now - self.prev_instant).min(max_duration);
+        self.poll_snapshot();
+
+        if self.rewinding {
+            if let Some(save) = self.history.pop_back() {
+                self.load(save, false);
+            }
+            self.prev_instant = now;
+            return;
+        }
+        if self.history.len() == REWIND_CAPACITY {
+            self.history.pop_front();
+        }
+        self.history.push_back(self.save());
 
-        self.control_with_agent();
+        let action = self.control_with_agent(duration);
+        self.apply_drag();
 
+        if let Some(recording) = &mut self.recording {
+            recording.start_tick(&self.pendulum, duration);
+        }
         self.pendulum.update(duration);
+        if let Some(recording) = &mut self.recording {
+            recording.finish_tick(&self.pendulum);
+        }
+        self.time += duration;
+        let reward = step_reward(&self.pendulum);
+        // Training earns a reward every 1/30 s, so weighting by the frame time keeps the score
+        // comparable to its scores whatever the frame rate.
+        self.episode_score += reward * duration.as_secs_f32() * 30.0;
+        if let Some(trajectory) = &mut self.trajectory {
+            trajectory.push(self.time.as_secs_f32(), &self.pendulum, action, reward);
+        }
         self.prev_instant = now;
     }
 
-    fn control_with_agent(&mut self) {
-        if let Ok(agent) = self.rx.try_recv() {
-            self.agents.push(agent);
+    fn control_with_agent(&mut self, delta: Duration) -> f32 {
+        if let Ok(mut champion) = self.rx.try_recv() {
+            // Training leaves the state of its last evaluation behind.
+            champion.agent.reset_state();
+            self.agents.push(champion);
+        }
+        if let Some(front) = self.front_rx.try_iter().last() {
+            self.front_index = self.front_index.min(front.len().saturating_sub(1));
+            self.front = front;
         }
-        set_pendulum_inputs(&mut self.pendulum, &mut self.agents.last_mut().unwrap());
+        let champion = match &mut self.pinned_agent {
+            Some(champion) => champion,
+            None => self.agents.last_mut().unwrap(),
+        };
+        set_pendulum_inputs(
+            &mut self.pendulum,
+            &mut self.hardware,
+            &mut champion.agent,
+            delta,
+        )
     }
 
-    pub fn shader_constants(&self, window_size: PhysicalSize<u32>) -> ShaderConstants {
+    fn grab_at(&self, p: Vec2) -> Option<Grab> {
+        let cart_pos = vec2(self.pendulum.cart_x(), 0.0);
+        if p.distance(self.pendulum.bob_pos()) < BOB_GRAB_RADIUS {
+            Some(Grab::Bob)
+        } else if (p - cart_pos).abs().cmplt(CART_GRAB_HALF_SIZE).all() {
+            Some(Grab::Cart)
+        } else {
+            None
+        }
+    }
+
+    fn apply_drag(&mut self) {
+        let target = self.cursor_world_pos();
+        match self.grab {
+            Some(Grab::Bob) => self.pendulum.push_bob(
+                DRAG_STIFFNESS * (target - self.pendulum.bob_pos())
+                    - DRAG_DAMPING * self.pendulum.bob_vel(),
+            ),
+            Some(Grab::Cart) => self.pendulum.push_cart(
+                DRAG_STIFFNESS * (target.x - self.pendulum.cart_x())
+                    - DRAG_DAMPING * self.pendulum.cart_linvel(),
+            ),
+            None => {}
+        }
+    }
+
+    /// Inverse of the uv transform in the fragment shader.
+    fn cursor_world_pos(&self) -> Vec2 {
+        let size = vec2(
+            self.window_size.width as f32,
+            self.window_size.height as f32,
+        );
+        (vec2(self.cursor_x, -self.cursor_y) - 0.5 * vec2(size.x, -size.y)) / size.y
+    }
+
+    pub fn shader_constants(&self) -> ShaderConstants {
         ShaderConstants {
-            width: window_size.width,
-            height: window_size.height,
+            width: self.window_size.width,
+            height: self.window_size.height,
             cursor_x: self.cursor_x,
             cursor_y: self.cursor_y,
             mouse_button_pressed: self.mouse_button_pressed,
             cart_x: self.pendulum.cart_x(),
             bob_x: self.pendulum.bob_pos().x,
             bob_y: self.pendulum.bob_pos().y,
+            controller: self.pinned_agent.is_some() as u32,
+            generation: self.active_champion().generation as u32,
+            training_score: self.active_champion().score,
+            time: self.time.as_secs_f32(),
+            cart_linvel: self.pendulum.cart_linvel(),
+            pole_angle: pole_angle_degrees(self.pendulum.bob_angle()),
+            episode_score: self.episode_score,
         }
     }
+
+    pub fn network_view(&self) -> NetworkView {
+        network_view(self.active_champion())
+    }
+}
+
+/// Network of the active agent with the activations of its last decision. Nodes past
+/// `MAX_NET_NODES` are left out, and only the strongest `MAX_NET_EDGES` edges are kept.
+fn network_view(champion: &Champion) -> NetworkView {
+    let graph = champion.agent.graph();
+    let mut view = NetworkView::zeroed();
+    for ((node, position), view_node) in graph.nodes.iter().zip(graph.layout()).zip(&mut view.nodes)
+    {
+        *view_node = NetNode {
+            x: position.x,
+            y: position.y,
+            activation: node.activation,
+            kind: match node.kind {
+                NodeKind::Input => NET_NODE_INPUT,
+                NodeKind::Hidden => NET_NODE_HIDDEN,
+                NodeKind::Output => NET_NODE_OUTPUT,
+            },
+        };
+    }
+    view.node_count = graph.nodes.len().min(MAX_NET_NODES) as u32;
+
+    let mut edges: Vec<_> = graph
+        .edges
+        .iter()
+        .filter(|e| e.source < MAX_NET_NODES && e.target < MAX_NET_NODES)
+        .collect();
+    edges.sort_by(|a, b| b.weight.abs().total_cmp(&a.weight.abs()));
+    for (edge, view_edge) in edges.iter().zip(&mut view.edges) {
+        *view_edge = NetEdge {
+            source: edge.source as u32,
+            target: edge.target as u32,
+            weight: edge.weight,
+            recurrent: edge.recurrent as u32,
+        };
+    }
+    view.edge_count = edges.len().min(MAX_NET_EDGES) as u32;
+    view
+}
+
+/// Deviation from upright in `-180.0..180.0`, positive when the bob leans right.
+fn pole_angle_degrees(bob_angle: f32) -> f32 {
+    use std::f32::consts::{PI, TAU};
+    (bob_angle.rem_euclid(TAU) - PI).to_degrees()
+}
+
+fn unix_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Save slots are restored with the number keys.
+fn save_slot(key: &str) -> Option<usize> {
+    match key.parse::<usize>() {
+        Ok(n) if (1..=SAVE_SLOTS).contains(&n) => Some(n - 1),
+        _ => None,
+    }
+}
+
+/// Save slots are written with the function keys.
+fn function_key_slot(key: NamedKey) -> Option<usize> {
+    [
+        NamedKey::F1,
+        NamedKey::F2,
+        NamedKey::F3,
+        NamedKey::F4,
+        NamedKey::F5,
+        NamedKey::F6,
+        NamedKey::F7,
+        NamedKey::F8,
+        NamedKey::F9,
+    ]
+    .iter()
+    .position(|&k| k == key)
 }
 
 fn mouse_button_index(button: MouseButton) -> usize {
@@ -0,0 +1,125 @@
+use crate::ml::metrics::GenerationStats;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+const PLOT_WIDTH: usize = 72;
+const PLOT_HEIGHT: usize = 16;
+const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Live terminal view of training progress for headless runs.
+pub struct Dashboard {
+    history: Vec<GenerationStats>,
+    last_draw: Option<Instant>,
+}
+
+impl Dashboard {
+    pub fn new() -> Self {
+        Self {
+            history: Vec::new(),
+            last_draw: None,
+        }
+    }
+
+    /// Redraws with every received generation until the sender hangs up.
+    pub fn run(&mut self, stats: Receiver<GenerationStats>) {
+        for generation in stats {
+            self.history.push(generation);
+            if self
+                .last_draw
+                .is_some_and(|t| t.elapsed() < REFRESH_INTERVAL)
+            {
+                continue;
+            }
+            self.last_draw = Some(Instant::now());
+            self.draw();
+        }
+        self.draw();
+    }
+
+    fn draw(&self) {
+        let Some(latest) = self.history.last() else {
+            return;
+        };
+        let mut out = String::new();
+        // Clear the screen and move the cursor home.
+        out.push_str("\x1b[2J\x1b[H");
+        writeln!(out, "Generation {}", latest.generation).unwrap();
+        writeln!(
+            out,
+            "Fitness   best {:>10.2}  mean {:>10.2}  median {:>10.2}  worst {:>10.2}",
+            latest.best, latest.mean, latest.median, latest.worst
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "Nodes     min {:>4}  mean {:>6.1}  max {:>4}    Edges  min {:>4}  mean {:>6.1}  max {:>4}",
+            latest.nodes.min,
+            latest.nodes.mean,
+            latest.nodes.max,
+            latest.edges.min,
+            latest.edges.mean,
+            latest.edges.max
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "Species   {:>4}    Throughput {:>8.1} simulations/s",
+            latest.species, latest.throughput
+        )
+        .unwrap();
+        if let Some(coverage) = latest.coverage {
+            writeln!(out, "Coverage  {:>5.1}% of the archive", coverage * 100.0).unwrap();
+        }
+        if let Some(stage) = latest.stage {
+            writeln!(out, "Stage     {stage:>4}").unwrap();
+        }
+        out.push('\n');
+        self.plot(&mut out);
+        print!("{out}");
+        std::io::stdout().flush().ok();
+    }
+
+    /// Best (`#`) and mean (`.`) fitness, one column per bucket of generations.
+    fn plot(&self, out: &mut String) {
+        let buckets = self.history.len().min(PLOT_WIDTH);
+        let per_bucket = self.history.len().div_ceil(buckets);
+        let columns: Vec<(f32, f32)> = self
+            .history
+            .chunks(per_bucket)
+            .map(|chunk| {
+                let best = chunk.iter().map(|s| s.best).fold(f32::MIN, f32::max);
+                let mean = chunk.iter().map(|s| s.mean).sum::<f32>() / chunk.len() as f32;
+                (best, mean)
+            })
+            .collect();
+        let top = columns.iter().map(|c| c.0).fold(f32::EPSILON, f32::max);
+        let row_of = |value: f32| {
+            ((value / top).clamp(0.0, 1.0) * (PLOT_HEIGHT - 1) as f32).round() as usize
+        };
+        for row in (0..PLOT_HEIGHT).rev() {
+            let label = top * row as f32 / (PLOT_HEIGHT - 1) as f32;
+            write!(out, "{label:>10.1} |").unwrap();
+            for &(best, mean) in &columns {
+                out.push(if row_of(best) == row {
+                    '#'
+                } else if row_of(mean) == row {
+                    '.'
+                } else {
+                    ' '
+                });
+            }
+            out.push('\n');
+        }
+        writeln!(out, "{:>10} +{}", "", "-".repeat(columns.len())).unwrap();
+        writeln!(
+            out,
+            "{:>12}generations 1-{}, {} per column",
+            "",
+            self.history.len(),
+            per_bucket
+        )
+        .unwrap();
+    }
+}
@@ -0,0 +1,321 @@
+use crate::pendulum::{bob_offset, CART_FRICTION, GRAVITY, RADIUS};
+use crate::sensor::{ChannelConfig, Measurement, SensorConfig};
+use glam::*;
+use std::f32::consts::PI;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StateEstimate {
+    pub cart_x: f32,
+    pub bob_angle: f32,
+    pub angvel: f32,
+}
+
+impl StateEstimate {
+    fn from_vec4(x: Vec4) -> Self {
+        Self {
+            cart_x: x.x,
+            bob_angle: x.z,
+            angvel: x.w,
+        }
+    }
+
+    pub fn bob_pos(&self) -> Vec2 {
+        Vec2::X * self.cart_x + bob_offset(self.bob_angle)
+    }
+}
+
+/// Turns a stream of measurements into an estimate of the full pendulum state.
+pub trait StateEstimator {
+    fn reset(&mut self);
+
+    /// `cart_linacc` is the acceleration applied since the previous measurement. `measurement` is
+    /// `None` until the sensors have released their first sample.
+    fn update(
+        &mut self,
+        measurement: Option<Measurement>,
+        cart_linacc: f32,
+        delta: Duration,
+    ) -> StateEstimate;
+}
+
+/// Uses the measurements as they are.
+#[derive(Clone, Default)]
+pub struct Passthrough;
+
+impl StateEstimator for Passthrough {
+    fn reset(&mut self) {}
+
+    fn update(&mut self, measurement: Option<Measurement>, _: f32, _: Duration) -> StateEstimate {
+        let measurement = measurement.unwrap_or_default();
+        StateEstimate {
+            cart_x: measurement.cart_x,
+            bob_angle: measurement.bob_angle,
+            angvel: measurement.angvel,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Model {
+    /// Linear Kalman filter with the dynamics linearised around the upright position.
+    Upright,
+    /// Extended Kalman filter over the full nonlinear dynamics.
+    Nonlinear,
+}
+
+#[derive(Clone, Debug)]
+pub struct KalmanConfig {
+    pub model: Model,
+    /// Variances of the process noise per second, in state order
+    /// (cart position, cart velocity, angle, angular velocity).
+    pub process_noise: Vec4,
+    /// Variances of the measured cart position, angle and angular velocity.
+    pub measurement_noise: Vec3,
+}
+
+impl KalmanConfig {
+    /// Matches the measurement noise to the sensor error models.
+    pub fn for_sensors(model: Model, sensors: &SensorConfig) -> Self {
+        fn variance(channel: &ChannelConfig) -> f32 {
+            // Quantisation error is uniform over one step. The floor keeps the gain finite for
+            // perfect sensors.
+            (channel.noise_std.powi(2) + channel.resolution.powi(2) / 12.0).max(1e-6)
+        }
+        Self {
+            model,
+            process_noise: vec4(1e-4, 1e-2, 1e-4, 1e-1),
+            measurement_noise: vec3(
+                variance(&sensors.cart_x),
+                variance(&sensors.bob_angle),
+                variance(&sensors.angvel),
+            ),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct KalmanFilter {
+    config: KalmanConfig,
+    x: Vec4,
+    p: Mat4,
+    initialised: bool,
+}
+
+impl KalmanFilter {
+    pub fn new(config: KalmanConfig) -> Self {
+        Self {
+            config,
+            x: Vec4::ZERO,
+            p: Mat4::IDENTITY,
+            initialised: false,
+        }
+    }
+
+    /// Time derivative of the state over a step of `dt` seconds, and its Jacobian.
+    fn dynamics(&self, x: Vec4, cart_linacc: f32, dt: f32) -> (Vec4, Mat4) {
+        let (derivative, d_angacc_d_angle) = match self.config.model {
+            Model::Upright => {
+                let angacc = (-cart_linacc - GRAVITY * (x.z - PI)) / RADIUS;
+                (vec4(x.y, cart_linacc, x.w, angacc), -GRAVITY / RADIUS)
+            }
+            Model::Nonlinear => {
+                let (sin, cos) = x.z.sin_cos();
+                // Like `Pendulum::update`, friction acts on the velocity the command leads to and
+                // at most stops the cart, so a cart at rest stays at rest.
+                let linvel = x.y + cart_linacc * dt;
+                let friction = if dt > 0.0 {
+                    (linvel / dt).clamp(-CART_FRICTION, CART_FRICTION)
+                } else {
+                    0.0
+                };
+                let cart_linacc = cart_linacc - friction;
+                let angacc = (cart_linacc * cos + GRAVITY * sin) / RADIUS - 0.01 * x.w;
+                (
+                    vec4(x.y, cart_linacc, x.w, angacc),
+                    (-cart_linacc * sin + GRAVITY * cos) / RADIUS,
+                )
+            }
+        };
+        let jacobian = Mat4::from_cols(
+            Vec4::ZERO,
+            Vec4::X,
+            vec4(0.0, 0.0, 0.0, d_angacc_d_angle),
+            Vec4::Z,
+        );
+        (derivative, jacobian)
+    }
+
+    fn predict(&mut self, cart_linacc: f32, dt: f32) {
+        let (derivative, jacobian) = self.dynamics(self.x, cart_linacc, dt);
+        let f = Mat4::IDENTITY + jacobian * dt;
+        self.x += derivative * dt;
+        self.p = f * self.p * f.transpose() + Mat4::from_diagonal(self.config.process_noise * dt);
+    }
+
+    /// Sequential update with a direct measurement `z` of state component `j`.
+    fn correct(&mut self, j: usize, z: f32, r: f32) {
+        let column = self.p.col(j);
+        let gain = column / (column[j] + r);
+        self.x += gain * (z - self.x[j]);
+        self.p -= Mat4::from_cols(
+            gain * column.x,
+            gain * column.y,
+            gain * column.z,
+            gain * column.w,
+        );
+    }
+}
+
+impl StateEstimator for KalmanFilter {
+    fn reset(&mut self) {
+        *self = Self::new(self.config.clone());
+    }
+
+    fn update(
+        &mut self,
+        measurement: Option<Measurement>,
+        cart_linacc: f32,
+        delta: Duration,
+    ) -> StateEstimate {
+        if self.initialised {
+            self.predict(cart_linacc, delta.as_secs_f32());
+        }
+        // Starts from the first sample the sensors release rather than from before it.
+        let Some(measurement) = measurement else {
+            return StateEstimate::from_vec4(self.x);
+        };
+        if !self.initialised {
+            self.x = vec4(
+                measurement.cart_x,
+                0.0,
+                measurement.bob_angle,
+                measurement.angvel,
+            );
+            self.initialised = true;
+        }
+        let r = self.config.measurement_noise;
+        self.correct(0, measurement.cart_x, r.x);
+        self.correct(2, measurement.bob_angle, r.y);
+        self.correct(3, measurement.angvel, r.z);
+        StateEstimate::from_vec4(self.x)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub enum EstimatorConfig {
+    #[default]
+    Passthrough,
+    Kalman(KalmanConfig),
+}
+
+#[derive(Clone)]
+pub enum Estimator {
+    Passthrough(Passthrough),
+    Kalman(KalmanFilter),
+}
+
+impl Estimator {
+    pub fn new(config: &EstimatorConfig) -> Self {
+        match config {
+            EstimatorConfig::Passthrough => Self::Passthrough(Passthrough),
+            EstimatorConfig::Kalman(config) => Self::Kalman(KalmanFilter::new(config.clone())),
+        }
+    }
+}
+
+impl StateEstimator for Estimator {
+    fn reset(&mut self) {
+        match self {
+            Self::Passthrough(estimator) => estimator.reset(),
+            Self::Kalman(estimator) => estimator.reset(),
+        }
+    }
+
+    fn update(
+        &mut self,
+        measurement: Option<Measurement>,
+        cart_linacc: f32,
+        delta: Duration,
+    ) -> StateEstimate {
+        match self {
+            Self::Passthrough(estimator) => estimator.update(measurement, cart_linacc, delta),
+            Self::Kalman(estimator) => estimator.update(measurement, cart_linacc, delta),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pendulum::{Pendulum, PendulumState};
+    use crate::sensor::Sensors;
+
+    const DELTA: Duration = Duration::from_millis(10);
+
+    /// Largest errors of the angle and cart position estimates over the second half of `steps`
+    /// spent tracking a pendulum that starts at `state` and whose cart is shaken back and forth
+    /// with an acceleration of up to `shake`.
+    fn tracking_errors(model: Model, state: PendulumState, steps: usize, shake: f32) -> (f32, f32) {
+        let sensors = SensorConfig::realistic();
+        let mut filter = KalmanFilter::new(KalmanConfig::for_sensors(model, &sensors));
+        let mut sensors = Sensors::seeded(sensors, 7);
+        let mut pendulum = Pendulum::from_state(state);
+        let mut cart_linacc = 0.0;
+        let (mut angle_error, mut cart_error) = (0.0f32, 0.0f32);
+        for step in 0..steps {
+            let estimate = filter.update(sensors.observe(&pendulum, DELTA), cart_linacc, DELTA);
+            if step >= steps / 2 {
+                angle_error = angle_error.max((estimate.bob_angle - pendulum.bob_angle()).abs());
+                cart_error = cart_error.max((estimate.cart_x - pendulum.cart_x()).abs());
+            }
+            cart_linacc = shake * (step as f32 * 0.02).sin();
+            pendulum.accelerate(cart_linacc);
+            pendulum.update(DELTA);
+        }
+        (angle_error, cart_error)
+    }
+
+    #[test]
+    fn extended_filter_tracks_a_swinging_pendulum() {
+        let state = PendulumState {
+            bob_angle: 1.0,
+            ..PendulumState::hanging()
+        };
+        let (angle_error, cart_error) = tracking_errors(Model::Nonlinear, state, 500, 2.0);
+        // What remains is mostly the sensor latency, at up to 5 rad/s.
+        assert!(angle_error < 0.08, "angle error {angle_error}");
+        assert!(cart_error < 0.01, "cart error {cart_error}");
+    }
+
+    #[test]
+    fn linear_filter_tracks_near_upright() {
+        let state = PendulumState {
+            bob_angle: PI + 0.01,
+            ..PendulumState::upright()
+        };
+        // Long enough to fall a few degrees.
+        let (angle_error, cart_error) = tracking_errors(Model::Upright, state, 60, 0.0);
+        assert!(angle_error < 0.02, "angle error {angle_error}");
+        assert!(cart_error < 0.01, "cart error {cart_error}");
+    }
+
+    #[test]
+    fn filter_starts_from_the_first_released_sample() {
+        let sensors = SensorConfig::realistic();
+        let mut filter = KalmanFilter::new(KalmanConfig::for_sensors(Model::Nonlinear, &sensors));
+        let mut sensors = Sensors::seeded(sensors, 7);
+        let pendulum = Pendulum::from_state(PendulumState {
+            bob_angle: 1.0,
+            ..PendulumState::hanging()
+        });
+        let estimate = loop {
+            let measurement = sensors.observe(&pendulum, DELTA);
+            let estimate = filter.update(measurement, 0.0, DELTA);
+            if measurement.is_some() {
+                break estimate;
+            }
+        };
+        assert!((estimate.bob_angle - 1.0).abs() < 0.01);
+    }
+}
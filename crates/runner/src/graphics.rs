@@ -1,6 +1,6 @@
 use crate::controller::Controller;
 use crate::{maybe_watch, CompiledShaderModules};
-use shared::ShaderConstants;
+use shared::{NetworkView, ShaderConstants};
 use winit::{
     event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop, EventLoopBuilder},
@@ -69,9 +69,37 @@ async fn run(
     let mut surface_with_config =
         auto_configure_surface(&adapter, &device, initial_surface, window.inner_size());
 
+    let network_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("network"),
+        size: std::mem::size_of::<NetworkView>() as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: None,
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &bind_group_layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: network_buffer.as_entire_binding(),
+        }],
+    });
+
     let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: None,
-        bind_group_layouts: &[],
+        bind_group_layouts: &[&bind_group_layout],
         push_constant_ranges: &[wgpu::PushConstantRange {
             stages: wgpu::ShaderStages::FRAGMENT,
             range: 0..std::mem::size_of::<ShaderConstants>() as u32,
@@ -85,7 +113,7 @@ async fn run(
         compiled_shader_modules,
     );
 
-    let mut controller = Controller::new();
+    let mut controller = Controller::new(window.inner_size());
 
     event_loop
         .run(|event, event_loop_window_target| {
@@ -113,6 +141,7 @@ async fn run(
                         surface_config.width = size.width;
                         surface_config.height = size.height;
                         surface.configure(&device, surface_config);
+                        controller.on_resize(size);
                     }
                 }
                 Event::WindowEvent {
@@ -160,9 +189,15 @@ async fn run(
                             });
 
                         controller.update();
-                        let push_constants = controller.shader_constants(window.inner_size());
+                        let push_constants = controller.shader_constants();
+                        queue.write_buffer(
+                            &network_buffer,
+                            0,
+                            bytemuck::bytes_of(&controller.network_view()),
+                        );
 
                         render_pass.set_pipeline(render_pipeline);
+                        render_pass.set_bind_group(0, &bind_group, &[]);
                         render_pass.set_push_constants(
                             wgpu::ShaderStages::FRAGMENT,
                             0,
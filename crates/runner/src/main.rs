@@ -1,7 +1,14 @@
+mod actuator;
+mod cli;
 mod controller;
+mod dashboard;
+mod estimator;
 mod graphics;
 mod ml;
 mod pendulum;
+mod recording;
+mod sensor;
+mod trajectory;
 
 use std::borrow::Cow;
 
@@ -58,5 +65,16 @@ fn maybe_watch(
 }
 
 pub fn main() {
-    graphics::start();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("replay") => cli::replay(&args[1..]),
+        Some("train") => cli::train(&args[1..]),
+        Some("graph") => cli::graph(&args[1..]),
+        Some("prune") => cli::prune(&args[1..]),
+        Some("cmaes") => cli::cmaes(&args[1..]),
+        Some("rl") => cli::rl(&args[1..]),
+        Some("q-learning") => cli::q_learning(&args[1..]),
+        Some("tournament") => cli::tournament(&args[1..]),
+        _ => graphics::start(),
+    }
 }
@@ -0,0 +1,72 @@
+use rand::prelude::*;
+use std::str::FromStr;
+
+/// Function a node applies to the weighted sum of its inputs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Activation {
+    #[default]
+    Tanh,
+    Sigmoid,
+    Relu,
+    Identity,
+    Sin,
+    Gaussian,
+    Step,
+}
+
+impl Activation {
+    pub const ALL: [Self; 7] = [
+        Self::Tanh,
+        Self::Sigmoid,
+        Self::Relu,
+        Self::Identity,
+        Self::Sin,
+        Self::Gaussian,
+        Self::Step,
+    ];
+
+    pub fn random() -> Self {
+        *Self::ALL.choose(&mut thread_rng()).unwrap()
+    }
+
+    pub fn apply(self, x: f32) -> f32 {
+        match self {
+            Self::Tanh => x.tanh(),
+            Self::Sigmoid => 1.0 / (1.0 + (-x).exp()),
+            Self::Relu => x.max(0.0),
+            Self::Identity => x,
+            Self::Sin => x.sin(),
+            Self::Gaussian => (-x * x).exp(),
+            Self::Step => {
+                if x > 0.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Tanh => "tanh",
+            Self::Sigmoid => "sigmoid",
+            Self::Relu => "relu",
+            Self::Identity => "identity",
+            Self::Sin => "sin",
+            Self::Gaussian => "gaussian",
+            Self::Step => "step",
+        }
+    }
+}
+
+impl FromStr for Activation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|a| a.name() == s)
+            .ok_or_else(|| format!("unknown activation function `{s}`"))
+    }
+}
@@ -0,0 +1,377 @@
+//! Reverse-mode automatic differentiation over small dense matrices, just enough to train the
+//! networks of `rl`.
+
+/// Row-major matrix. Vectors are single rows, scalars are `1×1`.
+#[derive(Clone, Debug)]
+pub struct Tensor {
+    pub rows: usize,
+    pub cols: usize,
+    pub data: Vec<f32>,
+}
+
+impl Tensor {
+    pub fn zeros(rows: usize, cols: usize) -> Self {
+        Self::new(rows, cols, vec![0.0; rows * cols])
+    }
+
+    pub fn new(rows: usize, cols: usize, data: Vec<f32>) -> Self {
+        assert_eq!(
+            data.len(),
+            rows * cols,
+            "tensor data doesn't match its shape"
+        );
+        Self { rows, cols, data }
+    }
+
+    /// A single column, one row per value.
+    pub fn column(data: Vec<f32>) -> Self {
+        Self::new(data.len(), 1, data)
+    }
+
+    pub fn scalar(value: f32) -> Self {
+        Self::new(1, 1, vec![value])
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> f32 {
+        self.data[row * self.cols + col]
+    }
+
+    fn same_shape(&self, other: &Self) -> bool {
+        self.rows == other.rows && self.cols == other.cols
+    }
+}
+
+/// Handle to a value recorded on a `Tape`.
+#[derive(Clone, Copy, Debug)]
+pub struct Var(usize);
+
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    Leaf,
+    MatMul(Var, Var),
+    /// Adds a row to every row.
+    AddRow(Var, Var),
+    /// Multiplies every row elementwise by a row.
+    MulRow(Var, Var),
+    Add(Var, Var),
+    Sub(Var, Var),
+    Mul(Var, Var),
+    Scale(Var, f32),
+    Tanh(Var),
+    Exp(Var),
+    Min(Var, Var),
+    Clamp(Var, f32, f32),
+    Mean(Var),
+}
+
+/// Records operations in the order they run, so that walking it backwards visits every value
+/// after everything computed from it.
+#[derive(Default)]
+pub struct Tape {
+    values: Vec<Tensor>,
+    ops: Vec<Op>,
+}
+
+impl Tape {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn leaf(&mut self, value: Tensor) -> Var {
+        self.push(value, Op::Leaf)
+    }
+
+    pub fn value(&self, var: Var) -> &Tensor {
+        &self.values[var.0]
+    }
+
+    fn push(&mut self, value: Tensor, op: Op) -> Var {
+        self.values.push(value);
+        self.ops.push(op);
+        Var(self.values.len() - 1)
+    }
+
+    pub fn matmul(&mut self, a: Var, b: Var) -> Var {
+        let (x, y) = (self.value(a), self.value(b));
+        assert_eq!(x.cols, y.rows, "matmul shapes don't match");
+        let mut out = Tensor::zeros(x.rows, y.cols);
+        for r in 0..x.rows {
+            let row = &mut out.data[r * y.cols..(r + 1) * y.cols];
+            for k in 0..x.cols {
+                let xk = x.get(r, k);
+                for (o, &yk) in row.iter_mut().zip(&y.data[k * y.cols..(k + 1) * y.cols]) {
+                    *o += xk * yk;
+                }
+            }
+        }
+        self.push(out, Op::MatMul(a, b))
+    }
+
+    pub fn add_row(&mut self, a: Var, row: Var) -> Var {
+        let out = self.broadcast(a, row, |x, r| x + r);
+        self.push(out, Op::AddRow(a, row))
+    }
+
+    pub fn mul_row(&mut self, a: Var, row: Var) -> Var {
+        let out = self.broadcast(a, row, |x, r| x * r);
+        self.push(out, Op::MulRow(a, row))
+    }
+
+    fn broadcast(&self, a: Var, row: Var, f: impl Fn(f32, f32) -> f32) -> Tensor {
+        let (x, r) = (self.value(a), self.value(row));
+        assert!(r.rows == 1 && r.cols == x.cols, "row shape doesn't match");
+        let data = x
+            .data
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| f(v, r.data[i % x.cols]))
+            .collect();
+        Tensor::new(x.rows, x.cols, data)
+    }
+
+    pub fn add(&mut self, a: Var, b: Var) -> Var {
+        let out = self.zip(a, b, |x, y| x + y);
+        self.push(out, Op::Add(a, b))
+    }
+
+    pub fn sub(&mut self, a: Var, b: Var) -> Var {
+        let out = self.zip(a, b, |x, y| x - y);
+        self.push(out, Op::Sub(a, b))
+    }
+
+    pub fn mul(&mut self, a: Var, b: Var) -> Var {
+        let out = self.zip(a, b, |x, y| x * y);
+        self.push(out, Op::Mul(a, b))
+    }
+
+    pub fn min(&mut self, a: Var, b: Var) -> Var {
+        let out = self.zip(a, b, f32::min);
+        self.push(out, Op::Min(a, b))
+    }
+
+    fn zip(&self, a: Var, b: Var, f: impl Fn(f32, f32) -> f32) -> Tensor {
+        let (x, y) = (self.value(a), self.value(b));
+        assert!(x.same_shape(y), "elementwise shapes don't match");
+        let data = x.data.iter().zip(&y.data).map(|(&x, &y)| f(x, y)).collect();
+        Tensor::new(x.rows, x.cols, data)
+    }
+
+    pub fn scale(&mut self, a: Var, factor: f32) -> Var {
+        let out = self.map(a, |x| x * factor);
+        self.push(out, Op::Scale(a, factor))
+    }
+
+    pub fn tanh(&mut self, a: Var) -> Var {
+        let out = self.map(a, f32::tanh);
+        self.push(out, Op::Tanh(a))
+    }
+
+    pub fn exp(&mut self, a: Var) -> Var {
+        let out = self.map(a, f32::exp);
+        self.push(out, Op::Exp(a))
+    }
+
+    /// Limits values to `low..=high`, passing no gradient to those that were outside.
+    pub fn clamp(&mut self, a: Var, low: f32, high: f32) -> Var {
+        let out = self.map(a, |x| x.clamp(low, high));
+        self.push(out, Op::Clamp(a, low, high))
+    }
+
+    fn map(&self, a: Var, f: impl Fn(f32) -> f32) -> Tensor {
+        let x = self.value(a);
+        Tensor::new(x.rows, x.cols, x.data.iter().map(|&v| f(v)).collect())
+    }
+
+    /// Mean of every element, as a scalar.
+    pub fn mean(&mut self, a: Var) -> Var {
+        let x = self.value(a);
+        let mean = x.data.iter().sum::<f32>() / x.data.len() as f32;
+        self.push(Tensor::scalar(mean), Op::Mean(a))
+    }
+
+    /// Gradients of the scalar `output` with respect to every value on the tape.
+    pub fn backward(&self, output: Var) -> Gradients {
+        assert_eq!(
+            self.value(output).data.len(),
+            1,
+            "can only differentiate a scalar"
+        );
+        let mut grads: Vec<Tensor> = self
+            .values
+            .iter()
+            .map(|v| Tensor::zeros(v.rows, v.cols))
+            .collect();
+        grads[output.0].data[0] = 1.0;
+
+        for i in (0..=output.0).rev() {
+            // Later values never flow into earlier ones, so this one's gradient is complete.
+            let grad = std::mem::replace(&mut grads[i], Tensor::zeros(0, 0));
+            let value = &self.values[i];
+            match self.ops[i] {
+                Op::Leaf => {}
+                Op::MatMul(a, b) => {
+                    let (x, y) = (self.value(a), self.value(b));
+                    let mut dx = vec![0.0; x.data.len()];
+                    let mut dy = vec![0.0; y.data.len()];
+                    for (r, g_row) in grad.data.chunks(y.cols).enumerate() {
+                        let x_row = &x.data[r * x.cols..(r + 1) * x.cols];
+                        let dx_row = &mut dx[r * x.cols..(r + 1) * x.cols];
+                        for (k, (&xk, dxk)) in x_row.iter().zip(dx_row).enumerate() {
+                            let y_row = &y.data[k * y.cols..(k + 1) * y.cols];
+                            let dy_row = &mut dy[k * y.cols..(k + 1) * y.cols];
+                            for ((&g, &yk), dyk) in g_row.iter().zip(y_row).zip(dy_row) {
+                                *dxk += g * yk;
+                                *dyk += g * xk;
+                            }
+                        }
+                    }
+                    accumulate(&mut grads[a.0], &dx);
+                    accumulate(&mut grads[b.0], &dy);
+                }
+                Op::AddRow(a, row) => {
+                    for (j, &g) in grad.data.iter().enumerate() {
+                        grads[a.0].data[j] += g;
+                        grads[row.0].data[j % grad.cols] += g;
+                    }
+                }
+                Op::MulRow(a, row) => {
+                    let (x, r) = (self.value(a), self.value(row));
+                    for (j, &g) in grad.data.iter().enumerate() {
+                        grads[a.0].data[j] += g * r.data[j % x.cols];
+                        grads[row.0].data[j % x.cols] += g * x.data[j];
+                    }
+                }
+                Op::Add(a, b) => {
+                    for (j, &g) in grad.data.iter().enumerate() {
+                        grads[a.0].data[j] += g;
+                        grads[b.0].data[j] += g;
+                    }
+                }
+                Op::Sub(a, b) => {
+                    for (j, &g) in grad.data.iter().enumerate() {
+                        grads[a.0].data[j] += g;
+                        grads[b.0].data[j] -= g;
+                    }
+                }
+                Op::Mul(a, b) => {
+                    let (x, y) = (self.value(a), self.value(b));
+                    for (j, &g) in grad.data.iter().enumerate() {
+                        grads[a.0].data[j] += g * y.data[j];
+                        grads[b.0].data[j] += g * x.data[j];
+                    }
+                }
+                Op::Scale(a, factor) => {
+                    for (j, &g) in grad.data.iter().enumerate() {
+                        grads[a.0].data[j] += g * factor;
+                    }
+                }
+                Op::Tanh(a) => {
+                    for (j, &g) in grad.data.iter().enumerate() {
+                        let y = value.data[j];
+                        grads[a.0].data[j] += g * (1.0 - y * y);
+                    }
+                }
+                Op::Exp(a) => {
+                    for (j, &g) in grad.data.iter().enumerate() {
+                        grads[a.0].data[j] += g * value.data[j];
+                    }
+                }
+                Op::Min(a, b) => {
+                    let (x, y) = (self.value(a), self.value(b));
+                    for (j, &g) in grad.data.iter().enumerate() {
+                        if x.data[j] <= y.data[j] {
+                            grads[a.0].data[j] += g;
+                        } else {
+                            grads[b.0].data[j] += g;
+                        }
+                    }
+                }
+                Op::Clamp(a, low, high) => {
+                    let x = self.value(a);
+                    for (j, &g) in grad.data.iter().enumerate() {
+                        if (low..=high).contains(&x.data[j]) {
+                            grads[a.0].data[j] += g;
+                        }
+                    }
+                }
+                Op::Mean(a) => {
+                    let gradient = &mut grads[a.0];
+                    let share = grad.data[0] / gradient.data.len() as f32;
+                    for g in &mut gradient.data {
+                        *g += share;
+                    }
+                }
+            }
+            grads[i] = grad;
+        }
+        Gradients(grads)
+    }
+}
+
+fn accumulate(gradient: &mut Tensor, values: &[f32]) {
+    for (g, v) in gradient.data.iter_mut().zip(values) {
+        *g += v;
+    }
+}
+
+pub struct Gradients(Vec<Tensor>);
+
+impl Gradients {
+    pub fn get(&self, var: Var) -> &Tensor {
+        &self.0[var.0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Exercises every operation on the leaves `x` (2×3), `w` (3×2), `b` (1×2) and `r` (1×2).
+    fn loss(leaves: &[Tensor]) -> (Tape, Var, Vec<Var>) {
+        let mut tape = Tape::new();
+        let vars: Vec<Var> = leaves.iter().map(|l| tape.leaf(l.clone())).collect();
+        let (x, w, b, r) = (vars[0], vars[1], vars[2], vars[3]);
+        let product = tape.matmul(x, w);
+        let sum = tape.add_row(product, b);
+        let hidden = tape.tanh(sum);
+        let scaled = tape.scale(hidden, 0.5);
+        let exp = tape.exp(scaled);
+        let weighted = tape.mul_row(exp, r);
+        let difference = tape.sub(weighted, hidden);
+        let square = tape.mul(difference, difference);
+        let clamped = tape.clamp(sum, -0.5, 0.5);
+        let min = tape.min(square, clamped);
+        let total = tape.add(min, square);
+        let output = tape.mean(total);
+        (tape, output, vars)
+    }
+
+    #[test]
+    fn gradients_match_finite_differences() {
+        let leaves = vec![
+            Tensor::new(2, 3, vec![0.3, -0.7, 0.2, 0.9, 0.1, -0.4]),
+            Tensor::new(3, 2, vec![0.5, -0.2, 0.8, 0.3, -0.6, 0.4]),
+            Tensor::new(1, 2, vec![0.1, -0.3]),
+            Tensor::new(1, 2, vec![1.2, -0.8]),
+        ];
+        let (tape, output, vars) = loss(&leaves);
+        let gradients = tape.backward(output);
+        let epsilon = 1e-3;
+        for (leaf, var) in vars.iter().enumerate() {
+            for i in 0..leaves[leaf].data.len() {
+                let nudged = |delta: f32| {
+                    let mut leaves = leaves.clone();
+                    leaves[leaf].data[i] += delta;
+                    let (tape, output, _) = loss(&leaves);
+                    tape.value(output).data[0]
+                };
+                let numeric = (nudged(epsilon) - nudged(-epsilon)) / (2.0 * epsilon);
+                let analytic = gradients.get(*var).data[i];
+                assert!(
+                    (numeric - analytic).abs() < 1e-2,
+                    "leaf {leaf} element {i}: numeric {numeric}, analytic {analytic}"
+                );
+            }
+        }
+    }
+}
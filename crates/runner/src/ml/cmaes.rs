@@ -0,0 +1,454 @@
+use super::metrics::GenerationStats;
+use super::pendulum::{self, PendulumAgent as CurrentAgent};
+use super::Ml;
+use crate::sensor::gaussian;
+use rand::prelude::*;
+use std::str::FromStr;
+use std::time::Instant;
+
+/// Smallest step size, relative to the initial one, before a run counts as converged.
+const TOL_X: f64 = 1e-9;
+/// Fitness range over recent generations below which a run counts as stalled.
+const TOL_FUN: f64 = 1e-9;
+const MAX_CONDITION: f64 = 1e14;
+
+/// What to do once a run has converged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Restart {
+    /// Stop after the first run.
+    None,
+    /// Restart with twice the population each time.
+    Ipop,
+    /// Alternate between doubling the population and short runs with small populations and step
+    /// sizes, whichever has used less of the budget so far.
+    Bipop,
+}
+
+impl FromStr for Restart {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "ipop" => Ok(Self::Ipop),
+            "bipop" => Ok(Self::Bipop),
+            _ => Err(format!("unknown restart strategy `{s}`")),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CmaesConfig {
+    /// Initial step size.
+    pub sigma: f64,
+    /// Population size of the first run. Defaults to `4 + 3 ln(n)`.
+    pub population: Option<usize>,
+    pub restart: Restart,
+    /// Simulations to run over all restarts.
+    pub max_evaluations: usize,
+}
+
+impl Default for CmaesConfig {
+    fn default() -> Self {
+        Self {
+            sigma: 0.5,
+            population: None,
+            restart: Restart::Bipop,
+            max_evaluations: 100_000,
+        }
+    }
+}
+
+/// Row-major square matrix.
+#[derive(Clone, Debug)]
+struct Matrix {
+    n: usize,
+    data: Vec<f64>,
+}
+
+impl Matrix {
+    fn identity(n: usize) -> Self {
+        let mut data = vec![0.0; n * n];
+        for i in 0..n {
+            data[i * n + i] = 1.0;
+        }
+        Self { n, data }
+    }
+
+    fn get(&self, i: usize, j: usize) -> f64 {
+        self.data[i * self.n + j]
+    }
+
+    fn set(&mut self, i: usize, j: usize, value: f64) {
+        self.data[i * self.n + j] = value;
+    }
+
+    fn mul_vec(&self, v: &[f64]) -> Vec<f64> {
+        (0..self.n)
+            .map(|i| (0..self.n).map(|j| self.get(i, j) * v[j]).sum())
+            .collect()
+    }
+
+    fn transpose_mul_vec(&self, v: &[f64]) -> Vec<f64> {
+        (0..self.n)
+            .map(|j| (0..self.n).map(|i| self.get(i, j) * v[i]).sum())
+            .collect()
+    }
+
+    /// Eigenvalues and eigenvectors (as columns) of a symmetric matrix with cyclic Jacobi
+    /// rotations.
+    fn symmetric_eigen(&self) -> (Vec<f64>, Matrix) {
+        let n = self.n;
+        let mut a = self.clone();
+        let mut v = Matrix::identity(n);
+        for _ in 0..100 {
+            let off_diagonal: f64 = (0..n)
+                .flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j)))
+                .map(|(i, j)| a.get(i, j).powi(2))
+                .sum();
+            if off_diagonal < 1e-30 {
+                break;
+            }
+            for p in 0..n {
+                for q in p + 1..n {
+                    let apq = a.get(p, q);
+                    if apq.abs() < 1e-300 {
+                        continue;
+                    }
+                    let theta = (a.get(q, q) - a.get(p, p)) / (2.0 * apq);
+                    let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                    let c = 1.0 / (t * t + 1.0).sqrt();
+                    let s = t * c;
+                    for k in 0..n {
+                        let akp = a.get(k, p);
+                        let akq = a.get(k, q);
+                        a.set(k, p, c * akp - s * akq);
+                        a.set(k, q, s * akp + c * akq);
+                    }
+                    for k in 0..n {
+                        let apk = a.get(p, k);
+                        let aqk = a.get(q, k);
+                        a.set(p, k, c * apk - s * aqk);
+                        a.set(q, k, s * apk + c * aqk);
+                    }
+                    for k in 0..n {
+                        let vkp = v.get(k, p);
+                        let vkq = v.get(k, q);
+                        v.set(k, p, c * vkp - s * vkq);
+                        v.set(k, q, s * vkp + c * vkq);
+                    }
+                }
+            }
+        }
+        ((0..n).map(|i| a.get(i, i)).collect(), v)
+    }
+}
+
+/// Covariance matrix adaptation evolution strategy maximising a fitness, after Hansen's
+/// "The CMA Evolution Strategy: A Tutorial".
+pub struct CmaEs {
+    n: usize,
+    lambda: usize,
+    weights: Vec<f64>,
+    mueff: f64,
+    cc: f64,
+    cs: f64,
+    c1: f64,
+    cmu: f64,
+    damps: f64,
+    chi_n: f64,
+    mean: Vec<f64>,
+    sigma: f64,
+    initial_sigma: f64,
+    pc: Vec<f64>,
+    ps: Vec<f64>,
+    covariance: Matrix,
+    /// Eigenvectors of the covariance as columns.
+    b: Matrix,
+    /// Square roots of the eigenvalues of the covariance.
+    d: Vec<f64>,
+    evaluations: usize,
+    eigen_evaluations: usize,
+    generation: usize,
+    best_history: Vec<f64>,
+}
+
+impl CmaEs {
+    pub fn new(mean: Vec<f64>, sigma: f64, lambda: usize) -> Self {
+        let n = mean.len();
+        let nf = n as f64;
+        let mu = lambda / 2;
+        let raw: Vec<f64> = (0..mu)
+            .map(|i| (mu as f64 + 0.5).ln() - ((i + 1) as f64).ln())
+            .collect();
+        let sum: f64 = raw.iter().sum();
+        let weights: Vec<f64> = raw.iter().map(|w| w / sum).collect();
+        let mueff = 1.0 / weights.iter().map(|w| w * w).sum::<f64>();
+        let cc = (4.0 + mueff / nf) / (nf + 4.0 + 2.0 * mueff / nf);
+        let cs = (mueff + 2.0) / (nf + mueff + 5.0);
+        let c1 = 2.0 / ((nf + 1.3).powi(2) + mueff);
+        let cmu = (1.0 - c1).min(2.0 * (mueff - 2.0 + 1.0 / mueff) / ((nf + 2.0).powi(2) + mueff));
+        let damps = 1.0 + 2.0 * (((mueff - 1.0) / (nf + 1.0)).sqrt() - 1.0).max(0.0) + cs;
+        Self {
+            n,
+            lambda,
+            weights,
+            mueff,
+            cc,
+            cs,
+            c1,
+            cmu,
+            damps,
+            chi_n: nf.sqrt() * (1.0 - 1.0 / (4.0 * nf) + 1.0 / (21.0 * nf * nf)),
+            mean,
+            sigma,
+            initial_sigma: sigma,
+            pc: vec![0.0; n],
+            ps: vec![0.0; n],
+            covariance: Matrix::identity(n),
+            b: Matrix::identity(n),
+            d: vec![1.0; n],
+            evaluations: 0,
+            eigen_evaluations: 0,
+            generation: 0,
+            best_history: Vec::new(),
+        }
+    }
+
+    pub fn default_population(n: usize) -> usize {
+        4 + (3.0 * (n as f64).ln()).floor() as usize
+    }
+
+    /// Samples a new population around the mean.
+    pub fn ask(&self) -> Vec<Vec<f64>> {
+        let mut rng = thread_rng();
+        (0..self.lambda)
+            .map(|_| {
+                let z: Vec<f64> = (0..self.n)
+                    .map(|i| self.d[i] * gaussian(&mut rng, 1.0) as f64)
+                    .collect();
+                let y = self.b.mul_vec(&z);
+                self.mean
+                    .iter()
+                    .zip(y)
+                    .map(|(m, y)| m + self.sigma * y)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Updates the distribution from the fitness of every member of the last `ask`.
+    pub fn tell(&mut self, population: &[(Vec<f64>, f64)]) {
+        let n = self.n;
+        let mut sorted: Vec<&(Vec<f64>, f64)> = population.iter().collect();
+        sorted.sort_by(|a, b| b.1.total_cmp(&a.1));
+        self.evaluations += population.len();
+        self.generation += 1;
+        self.best_history.push(sorted[0].1);
+
+        let old_mean = self.mean.clone();
+        let steps: Vec<Vec<f64>> = sorted
+            .iter()
+            .take(self.weights.len())
+            .map(|(x, _)| {
+                x.iter()
+                    .zip(&old_mean)
+                    .map(|(x, m)| (x - m) / self.sigma)
+                    .collect()
+            })
+            .collect();
+        let mut mean_step = vec![0.0; n];
+        for (w, y) in self.weights.iter().zip(&steps) {
+            for (step, y) in mean_step.iter_mut().zip(y) {
+                *step += w * y;
+            }
+        }
+        for ((mean, old), step) in self.mean.iter_mut().zip(&old_mean).zip(&mean_step) {
+            *mean = old + self.sigma * step;
+        }
+
+        // C^-1/2 * mean_step = B * D^-1 * B^T * mean_step
+        let mut whitened = self.b.transpose_mul_vec(&mean_step);
+        for (w, d) in whitened.iter_mut().zip(&self.d) {
+            *w /= d;
+        }
+        let whitened = self.b.mul_vec(&whitened);
+        let cs_factor = (self.cs * (2.0 - self.cs) * self.mueff).sqrt();
+        for (ps, w) in self.ps.iter_mut().zip(&whitened) {
+            *ps = (1.0 - self.cs) * *ps + cs_factor * w;
+        }
+        let ps_norm = self.ps.iter().map(|p| p * p).sum::<f64>().sqrt();
+        let hsig =
+            ps_norm / (1.0 - (1.0 - self.cs).powi(2 * self.generation as i32)).sqrt() / self.chi_n
+                < 1.4 + 2.0 / (n as f64 + 1.0);
+        let cc_factor = (self.cc * (2.0 - self.cc) * self.mueff).sqrt();
+        for (pc, step) in self.pc.iter_mut().zip(&mean_step) {
+            *pc = (1.0 - self.cc) * *pc + if hsig { cc_factor * step } else { 0.0 };
+        }
+
+        let correction = if hsig { 0.0 } else { self.cc * (2.0 - self.cc) };
+        for i in 0..n {
+            for j in 0..=i {
+                let rank_mu: f64 = self
+                    .weights
+                    .iter()
+                    .zip(&steps)
+                    .map(|(w, y)| w * y[i] * y[j])
+                    .sum();
+                let value = (1.0 - self.c1 - self.cmu) * self.covariance.get(i, j)
+                    + self.c1 * (self.pc[i] * self.pc[j] + correction * self.covariance.get(i, j))
+                    + self.cmu * rank_mu;
+                self.covariance.set(i, j, value);
+                self.covariance.set(j, i, value);
+            }
+        }
+
+        self.sigma *= ((self.cs / self.damps) * (ps_norm / self.chi_n - 1.0)).exp();
+
+        let eigen_interval = self.lambda as f64 / (self.c1 + self.cmu) / n as f64 / 10.0;
+        if (self.evaluations - self.eigen_evaluations) as f64 > eigen_interval {
+            self.eigen_evaluations = self.evaluations;
+            let (values, vectors) = self.covariance.symmetric_eigen();
+            self.d = values.iter().map(|v| v.max(1e-20).sqrt()).collect();
+            self.b = vectors;
+        }
+    }
+
+    /// Whether the run has converged or stalled and should be restarted.
+    pub fn should_stop(&self) -> bool {
+        let max_d = self.d.iter().copied().fold(0.0, f64::max);
+        let min_d = self.d.iter().copied().fold(f64::INFINITY, f64::min);
+        if self.sigma * max_d < TOL_X * self.initial_sigma {
+            return true;
+        }
+        if (max_d / min_d).powi(2) > MAX_CONDITION {
+            return true;
+        }
+        let window = 10 + (30 * self.n).div_ceil(self.lambda);
+        if self.best_history.len() >= window {
+            let recent = &self.best_history[self.best_history.len() - window..];
+            let max = recent.iter().copied().fold(f64::MIN, f64::max);
+            let min = recent.iter().copied().fold(f64::MAX, f64::min);
+            if max - min < TOL_FUN {
+                return true;
+            }
+        }
+        !self.sigma.is_finite() || !self.mean.iter().all(|m| m.is_finite())
+    }
+
+    pub fn evaluations(&self) -> usize {
+        self.evaluations
+    }
+}
+
+impl Ml {
+    /// Tunes the weights and biases of `topology` with CMA-ES, reporting progress and champions
+    /// like `run_generations`. Every restart starts again from the parameters of `topology`.
+    pub fn run_cmaes(&mut self, topology: CurrentAgent, config: &CmaesConfig) {
+        let initial: Vec<f64> = topology.parameters().iter().map(|&p| p as f64).collect();
+        let default_lambda = config
+            .population
+            .unwrap_or_else(|| CmaEs::default_population(initial.len()));
+        let mut rng = thread_rng();
+        let mut large_lambda = default_lambda;
+        let mut large_budget = 0;
+        let mut small_budget = 0;
+        let mut evaluations = 0;
+        let mut restarts = 0;
+
+        while evaluations < config.max_evaluations && self.keep_going(None) {
+            let small =
+                config.restart == Restart::Bipop && restarts > 0 && small_budget < large_budget;
+            let (lambda, sigma) = if small {
+                let u: f64 = rng.gen();
+                let ratio = 0.5 * large_lambda as f64 / default_lambda as f64;
+                let lambda = (default_lambda as f64 * ratio.powf(u * u)).floor() as usize;
+                (
+                    lambda.max(2),
+                    config.sigma * 10f64.powf(-2.0 * rng.gen::<f64>()),
+                )
+            } else {
+                if restarts > 0 {
+                    large_lambda *= 2;
+                }
+                (large_lambda, config.sigma)
+            };
+            println!("CMA-ES run {} with population {lambda}", restarts + 1);
+
+            let mut cmaes = CmaEs::new(initial.clone(), sigma, lambda);
+            while evaluations + cmaes.evaluations() < config.max_evaluations
+                && !cmaes.should_stop()
+                && self.keep_going(None)
+            {
+                self.cmaes_generation(&mut cmaes, &topology);
+            }
+            evaluations += cmaes.evaluations();
+            if small {
+                small_budget += cmaes.evaluations();
+            } else {
+                large_budget += cmaes.evaluations();
+            }
+            restarts += 1;
+            if config.restart == Restart::None {
+                break;
+            }
+        }
+    }
+
+    fn cmaes_generation(&mut self, cmaes: &mut CmaEs, topology: &CurrentAgent) {
+        use rayon::prelude::*;
+        let start = Instant::now();
+        let samples = cmaes.ask();
+        let scores_and_agents: Vec<(f32, CurrentAgent)> = samples
+            .par_iter()
+            .map(|x| {
+                let mut agent = topology.clone();
+                agent.set_parameters(&x.iter().map(|&p| p as f32).collect::<Vec<_>>());
+                (pendulum::run_simulation(&mut agent, &self.env), agent)
+            })
+            .collect();
+        let evaluation_time = start.elapsed();
+        // The sampled points rather than the agents' parameters, which were rounded to `f32`.
+        let population: Vec<(Vec<f64>, f64)> = samples
+            .into_iter()
+            .zip(&scores_and_agents)
+            .map(|(x, (score, _))| (x, *score as f64))
+            .collect();
+        cmaes.tell(&population);
+
+        self.generation += 1;
+        self.record_stats(GenerationStats::new(
+            self.generation,
+            &scores_and_agents,
+            evaluation_time,
+        ));
+        let (best_score, best_agent) = scores_and_agents
+            .iter()
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .unwrap();
+        self.consider_champion(best_agent, *best_score);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_optimum_of_a_sphere() {
+        let mut cmaes = CmaEs::new(vec![1.0; 5], 0.5, CmaEs::default_population(5));
+        for _ in 0..500 {
+            let population: Vec<(Vec<f64>, f64)> = cmaes
+                .ask()
+                .into_iter()
+                .map(|x| {
+                    let fitness = -x.iter().map(|x| x * x).sum::<f64>();
+                    (x, fitness)
+                })
+                .collect();
+            cmaes.tell(&population);
+        }
+        let distance = cmaes.mean.iter().map(|m| m * m).sum::<f64>().sqrt();
+        assert!(distance < 1e-6, "mean {:?}", cmaes.mean);
+    }
+}
@@ -0,0 +1,233 @@
+use super::metrics::GenerationStats;
+use super::pendulum::{self, PendulumAgent as CurrentAgent, Scenario};
+use super::{reproduce, Ml};
+use crate::pendulum::PendulumState;
+use rand::prelude::*;
+use std::f32::consts::PI;
+use std::time::Instant;
+
+/// One level of difficulty: how far from upright episodes may start, how long they last and how
+/// hard the bob gets pushed.
+#[derive(Clone, Debug)]
+pub struct Stage {
+    /// Largest angle from upright a start can have, in radians. `PI` includes hanging down.
+    pub max_angle: f32,
+    pub steps: usize,
+    pub disturbance: f32,
+}
+
+impl Stage {
+    /// A scenario starting at rest at a random angle within the stage.
+    pub fn scenario(&self, rng: &mut impl Rng) -> Scenario {
+        let offset = rng.gen_range(-self.max_angle..=self.max_angle);
+        Scenario {
+            start: PendulumState {
+                bob_angle: PI + offset,
+                ..PendulumState::hanging()
+            },
+            steps: self.steps,
+            disturbance: self.disturbance,
+            seed: rng.gen(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CurriculumConfig {
+    /// From the easiest to the hardest. Training stays in the last one once it gets there.
+    pub stages: Vec<Stage>,
+    /// Fraction of an episode the bob has to spend upright for it to count as a success.
+    pub success_upright: f32,
+    /// Fraction of all episodes of a generation that have to succeed to move on to the next
+    /// stage.
+    pub promotion_rate: f32,
+    /// Consecutive generations that have to reach `promotion_rate`, since a few lucky
+    /// scenarios can make one generation look much better than the population is.
+    pub patience: usize,
+    /// Scenarios shared by every agent of a generation.
+    pub scenarios: usize,
+    pub population: usize,
+}
+
+impl Default for CurriculumConfig {
+    fn default() -> Self {
+        Self {
+            stages: vec![
+                Stage {
+                    max_angle: 0.2,
+                    steps: 10 * 30,
+                    disturbance: 0.0,
+                },
+                Stage {
+                    max_angle: 0.8,
+                    steps: 20 * 30,
+                    disturbance: 0.0,
+                },
+                Stage {
+                    max_angle: 1.6,
+                    steps: 50 * 30,
+                    disturbance: 5.0,
+                },
+                Stage {
+                    max_angle: PI,
+                    steps: 100 * 30,
+                    disturbance: 10.0,
+                },
+            ],
+            success_upright: 0.5,
+            promotion_rate: 0.25,
+            patience: 5,
+            scenarios: 3,
+            population: 20,
+        }
+    }
+}
+
+/// Where training is in the curriculum.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Progress {
+    stage: usize,
+    /// Consecutive generations in the current stage that reached the promotion rate.
+    streak: usize,
+}
+
+impl Progress {
+    /// Counts a generation with `success_rate`, returning whether it moved training on to the
+    /// next stage.
+    fn advance(&mut self, success_rate: f32, config: &CurriculumConfig) -> bool {
+        self.streak = if success_rate >= config.promotion_rate {
+            self.streak + 1
+        } else {
+            0
+        };
+        if self.streak >= config.patience && self.stage + 1 < config.stages.len() {
+            self.stage += 1;
+            self.streak = 0;
+            return true;
+        }
+        false
+    }
+}
+
+impl Ml {
+    /// Evolves agents on scenarios that start easy and get harder as the population masters
+    /// them. Statistics record the mean score over the scenarios of the current stage, while
+    /// champions are judged on the standard episode of `run_simulation` so that they stay
+    /// comparable with every other search.
+    pub fn run_curriculum(&mut self, config: &CurriculumConfig, generations: Option<usize>) {
+        let mut rng = thread_rng();
+        let mut progress = Progress::default();
+        let mut agents: Vec<CurrentAgent> =
+            (0..config.population).map(|_| self.new_agent()).collect();
+        while self.keep_going(generations) {
+            let scenarios: Vec<Scenario> = (0..config.scenarios)
+                .map(|_| config.stages[progress.stage].scenario(&mut rng))
+                .collect();
+            let success_rate;
+            (agents, success_rate) =
+                self.curriculum_selection(agents, &scenarios, progress.stage, config);
+            if progress.advance(success_rate, config) {
+                println!(
+                    "Curriculum stage {} of {} after generation {}",
+                    progress.stage + 1,
+                    config.stages.len(),
+                    self.generation
+                );
+            }
+        }
+    }
+
+    /// Selects like `selection` on the mean score over the `scenarios` of `stage`, returning the
+    /// next generation and the fraction of episodes that succeeded.
+    fn curriculum_selection(
+        &mut self,
+        agents: Vec<CurrentAgent>,
+        scenarios: &[Scenario],
+        stage: usize,
+        config: &CurriculumConfig,
+    ) -> (Vec<CurrentAgent>, f32) {
+        use rayon::prelude::*;
+        let start = Instant::now();
+        let (successes, mut scores_and_agents): (Vec<usize>, Vec<(f32, CurrentAgent)>) = agents
+            .into_par_iter()
+            .map(|mut agent| {
+                let mut score = 0.0;
+                let mut successes = 0;
+                for scenario in scenarios {
+                    let evaluation = pendulum::evaluate_scenario(&mut agent, &self.env, scenario);
+                    score += evaluation.score;
+                    if evaluation.objectives.upright >= config.success_upright {
+                        successes += 1;
+                    }
+                }
+                (successes, (score / scenarios.len() as f32, agent))
+            })
+            .unzip();
+        let evaluation_time = start.elapsed();
+        scores_and_agents.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        self.generation += 1;
+        self.record_stats(GenerationStats {
+            stage: Some(stage + 1),
+            ..GenerationStats::new(self.generation, &scores_and_agents, evaluation_time)
+        });
+        let mut best_agent = scores_and_agents.last().unwrap().1.clone();
+        let standard_score = pendulum::run_simulation(&mut best_agent, &self.env);
+        self.consider_champion(&best_agent, standard_score);
+
+        let episodes = scores_and_agents.len() * scenarios.len();
+        let success_rate = successes.iter().sum::<usize>() as f32 / episodes as f32;
+        (
+            reproduce(scores_and_agents, &self.parent_selection),
+            success_rate,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scenarios_start_within_the_stage() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for stage in CurriculumConfig::default().stages {
+            for _ in 0..200 {
+                let scenario = stage.scenario(&mut rng);
+                assert!((scenario.start.bob_angle - PI).abs() <= stage.max_angle + 1e-6);
+                assert_eq!(scenario.start.bob_angvel, 0.0);
+                assert_eq!(scenario.steps, stage.steps);
+                assert_eq!(scenario.disturbance, stage.disturbance);
+            }
+        }
+    }
+
+    #[test]
+    fn promotion_needs_a_streak_and_stops_at_the_last_stage() {
+        let config = CurriculumConfig {
+            stages: CurriculumConfig::default().stages[..2].to_vec(),
+            promotion_rate: 0.5,
+            patience: 2,
+            ..CurriculumConfig::default()
+        };
+        let mut progress = Progress::default();
+        // A failing generation breaks the streak.
+        assert!(!progress.advance(0.5, &config));
+        assert!(!progress.advance(0.4, &config));
+        assert_eq!(progress, Progress::default());
+        assert!(!progress.advance(0.6, &config));
+        assert!(progress.advance(1.0, &config));
+        assert_eq!(
+            progress,
+            Progress {
+                stage: 1,
+                streak: 0
+            }
+        );
+        // There is nowhere to go from the last stage.
+        for _ in 0..3 {
+            assert!(!progress.advance(1.0, &config));
+        }
+        assert_eq!(progress.stage, 1);
+    }
+}
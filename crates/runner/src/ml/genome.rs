@@ -0,0 +1,195 @@
+use super::activation::Activation;
+use super::{Agent, Edge, Inputs, Node, Outputs, RecurrentEdge};
+use daggy::petgraph::stable_graph::node_index;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+const HEADER: &str = "pendulum-agent 3";
+/// Before the outputs stored their squashing, when they all used `Outputs::squash`.
+const HEADER_V2: &str = "pendulum-agent 2";
+/// Before activation functions, when every hidden node used tanh and outputs were not squashed.
+const HEADER_V1: &str = "pendulum-agent 1";
+
+impl<I: Inputs, O: Outputs> Agent<I, O> {
+    /// Writes the network as text: the input and output counts, one `node <bias> <function>`
+    /// line per node, one `edge <source> <target> <weight>` line per edge, all in index order so
+    /// that a loaded agent behaves exactly like the saved one, and one `recurrent <source>
+    /// <target> <weight>` line per recurrent edge.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = BufWriter::new(std::fs::File::create(path)?);
+        writeln!(file, "{HEADER}")?;
+        writeln!(file, "inputs {}", I::COUNT)?;
+        writeln!(file, "outputs {}", O::COUNT)?;
+        for node in self.dag.raw_nodes() {
+            // `Display` for floats round-trips exactly.
+            writeln!(
+                file,
+                "node {} {}",
+                node.weight.bias,
+                node.weight.function.name()
+            )?;
+        }
+        for edge in self.dag.raw_edges() {
+            writeln!(
+                file,
+                "edge {} {} {}",
+                edge.source().index(),
+                edge.target().index(),
+                edge.weight.weight
+            )?;
+        }
+        for r in &self.recurrent {
+            writeln!(
+                file,
+                "recurrent {} {} {}",
+                r.source, r.target, r.edge.weight
+            )?;
+        }
+        file.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let file = io::BufReader::new(std::fs::File::open(path)?);
+        let mut lines = file.lines();
+        let version = match lines.next().transpose()?.as_deref() {
+            Some(HEADER) => 3,
+            Some(HEADER_V2) => 2,
+            Some(HEADER_V1) => 1,
+            _ => return Err(invalid_data("not a pendulum agent")),
+        };
+        let mut dag = daggy::Dag::new();
+        let mut recurrent = Vec::new();
+        for line in lines {
+            let line = line?;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["inputs", count] => expect_count("inputs", parse(count)?, I::COUNT)?,
+                ["outputs", count] => expect_count("outputs", parse(count)?, O::COUNT)?,
+                ["node", bias, function @ ..] if function.len() <= 1 => {
+                    // Ids only relate nodes within a run, so loaded hidden nodes get new ones.
+                    let index = dag.node_count();
+                    let is_output = (I::COUNT..I::COUNT + O::COUNT).contains(&index);
+                    dag.add_node(Node {
+                        id: if index < I::COUNT + O::COUNT {
+                            index
+                        } else {
+                            Self::hidden_id()
+                        },
+                        value: 0.0,
+                        bias: parse(bias)?,
+                        function: match (version, function) {
+                            (1, _) if is_output => Activation::Identity,
+                            (2, _) if is_output => O::squash(index - I::COUNT),
+                            (_, [name]) => parse(name)?,
+                            _ => Activation::Tanh,
+                        },
+                        activation: 0.0,
+                    });
+                }
+                ["edge", source, target, weight] => {
+                    let source: usize = parse(source)?;
+                    let target: usize = parse(target)?;
+                    if source.max(target) >= dag.node_count() {
+                        return Err(invalid_data(&format!("edge to missing node `{line}`")));
+                    }
+                    dag.add_edge(
+                        node_index(source),
+                        node_index(target),
+                        Edge::new(parse(weight)?),
+                    )
+                    .map_err(|_| invalid_data(&format!("edge would cycle `{line}`")))?;
+                }
+                ["recurrent", source, target, weight] => {
+                    let source: usize = parse(source)?;
+                    let target: usize = parse(target)?;
+                    if source.max(target) >= dag.node_count() {
+                        return Err(invalid_data(&format!("edge to missing node `{line}`")));
+                    }
+                    recurrent.push(RecurrentEdge {
+                        source,
+                        target,
+                        edge: Edge::new(parse(weight)?),
+                    });
+                }
+                _ => return Err(invalid_data(&format!("unexpected line `{line}`"))),
+            }
+        }
+        if dag.node_count() < I::COUNT + O::COUNT {
+            return Err(invalid_data("missing input or output nodes"));
+        }
+        Ok(Self {
+            dag,
+            recurrent,
+            _inputs: PhantomData,
+            _outputs: PhantomData,
+        })
+    }
+}
+
+fn expect_count(what: &str, actual: usize, expected: usize) -> io::Result<()> {
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(invalid_data(&format!(
+            "expected {expected} {what}, found {actual}"
+        )))
+    }
+}
+
+fn parse<T: std::str::FromStr>(field: &str) -> io::Result<T>
+where
+    T::Err: std::fmt::Display,
+{
+    field
+        .parse()
+        .map_err(|e| invalid_data(&format!("{e}: `{field}`")))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::pendulum::PendulumAgent;
+    use super::super::NodeKind;
+    use super::*;
+
+    #[test]
+    fn version_1_outputs_are_not_squashed() {
+        let path = std::env::temp_dir().join("version_1_outputs_are_not_squashed.txt");
+        let nodes = (0..)
+            .take_while(|&i| PendulumAgent::node_kind(i) != NodeKind::Hidden)
+            .count();
+        let mut text = format!("{HEADER_V1}\n");
+        for _ in 0..nodes {
+            text.push_str("node 0\n");
+        }
+        std::fs::write(&path, text).unwrap();
+        let agent = PendulumAgent::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        for (i, node) in agent.dag.raw_nodes().iter().enumerate() {
+            if PendulumAgent::node_kind(i) == NodeKind::Output {
+                assert_eq!(node.weight.function, Activation::Identity);
+            }
+        }
+    }
+
+    #[test]
+    fn saved_agents_keep_their_squashing() {
+        let path = std::env::temp_dir().join("saved_agents_keep_their_squashing.txt");
+        let agent = PendulumAgent::with_squash(Some(Activation::Sigmoid));
+        agent.save(&path).unwrap();
+        let loaded = PendulumAgent::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        for (i, node) in loaded.dag.raw_nodes().iter().enumerate() {
+            if PendulumAgent::node_kind(i) == NodeKind::Output {
+                assert_eq!(node.weight.function, Activation::Sigmoid);
+            }
+        }
+    }
+}
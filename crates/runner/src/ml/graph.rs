@@ -0,0 +1,243 @@
+use super::activation::Activation;
+use glam::{vec2, Vec2};
+use std::fmt::Write as _;
+
+/// Size of the SVG drawing in pixels.
+const SVG_SIZE: Vec2 = vec2(640.0, 400.0);
+const SVG_NODE_RADIUS: f32 = 22.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    Input,
+    Hidden,
+    Output,
+}
+
+#[derive(Clone, Debug)]
+pub struct GraphNode {
+    /// Input or output name, or `h` and the node index for hidden nodes.
+    pub label: String,
+    pub kind: NodeKind,
+    /// Column of the node: inputs are at 0, outputs after every hidden node.
+    pub depth: usize,
+    /// Added to what the node passes on. Outputs ignore it.
+    pub bias: f32,
+    /// Activation function of hidden nodes, or squashing of outputs. Unused by inputs.
+    pub function: Activation,
+    /// What the node passed on during the last `choose`, or returned for outputs.
+    pub activation: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct GraphEdge {
+    pub source: usize,
+    pub target: usize,
+    pub weight: f32,
+    /// Carries the source's activation from the previous `choose`.
+    pub recurrent: bool,
+}
+
+/// Snapshot of an agent's network, indexed like its nodes.
+#[derive(Clone, Debug)]
+pub struct AgentGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+impl AgentGraph {
+    /// Number of columns in the layout.
+    pub fn columns(&self) -> usize {
+        self.nodes.iter().map(|n| n.depth + 1).max().unwrap_or(0)
+    }
+
+    /// Node positions in the unit square, one column per depth from left to right, with the
+    /// nodes of a column spread evenly from top to bottom.
+    pub fn layout(&self) -> Vec<Vec2> {
+        let columns = self.columns();
+        let mut column_sizes = vec![0; columns];
+        let mut rows = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            rows.push(column_sizes[node.depth]);
+            column_sizes[node.depth] += 1;
+        }
+        self.nodes
+            .iter()
+            .zip(rows)
+            .map(|(node, row)| {
+                let x = (node.depth as f32 + 0.5) / columns as f32;
+                let y = (row as f32 + 0.5) / column_sizes[node.depth] as f32;
+                vec2(x, y)
+            })
+            .collect()
+    }
+
+    /// Graphviz document with one rank per column. Weights and biases are rounded to three
+    /// decimals so that documents of similar agents diff cleanly.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        out.push_str("digraph agent {\n");
+        out.push_str("    rankdir=LR;\n");
+        out.push_str("    node [shape=circle];\n");
+        for (i, node) in self.nodes.iter().enumerate() {
+            let attributes = match node.kind {
+                NodeKind::Input => format!(r#"label="{}", shape=box"#, node.label),
+                NodeKind::Hidden => format!(
+                    r#"label="{}\n{}\nbias {:.3}""#,
+                    node.label,
+                    node.function.name(),
+                    node.bias
+                ),
+                NodeKind::Output => format!(
+                    r#"label="{}\n{}", shape=doublecircle"#,
+                    node.label,
+                    node.function.name()
+                ),
+            };
+            writeln!(out, "    n{i} [{attributes}];").unwrap();
+        }
+        for column in 0..self.columns() {
+            let members: Vec<String> = (0..self.nodes.len())
+                .filter(|&i| self.nodes[i].depth == column)
+                .map(|i| format!("n{i};"))
+                .collect();
+            writeln!(out, "    {{ rank=same; {} }}", members.join(" ")).unwrap();
+        }
+        for edge in &self.edges {
+            writeln!(
+                out,
+                r#"    n{} -> n{} [label="{:.3}", color={}, penwidth={:.2}{}];"#,
+                edge.source,
+                edge.target,
+                edge.weight,
+                edge_color(edge.weight),
+                edge_width(edge.weight),
+                if edge.recurrent {
+                    ", style=dashed, constraint=false"
+                } else {
+                    ""
+                }
+            )
+            .unwrap();
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Standalone SVG drawing of `layout`, with the same colours as the DOT document.
+    pub fn to_svg(&self) -> String {
+        let padding = Vec2::splat(2.0 * SVG_NODE_RADIUS);
+        let positions: Vec<Vec2> = self
+            .layout()
+            .into_iter()
+            .map(|p| padding + p * (SVG_SIZE - 2.0 * padding))
+            .collect();
+        let mut out = String::new();
+        writeln!(
+            out,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+            SVG_SIZE.x, SVG_SIZE.y
+        )
+        .unwrap();
+        out.push_str(r#"<rect width="100%" height="100%" fill="white"/>"#);
+        out.push('\n');
+        for edge in &self.edges {
+            let (a, b) = (positions[edge.source], positions[edge.target]);
+            writeln!(
+                out,
+                r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}" stroke-width="{:.2}"{}><title>{:.3}</title></line>"#,
+                a.x,
+                a.y,
+                b.x,
+                b.y,
+                edge_color(edge.weight),
+                edge_width(edge.weight),
+                if edge.recurrent {
+                    r#" stroke-dasharray="4 3""#
+                } else {
+                    ""
+                },
+                edge.weight
+            )
+            .unwrap();
+        }
+        for (node, p) in self.nodes.iter().zip(&positions) {
+            let stroke = match node.kind {
+                NodeKind::Input => "green",
+                NodeKind::Hidden => "gray",
+                NodeKind::Output => "red",
+            };
+            writeln!(
+                out,
+                r#"<circle cx="{:.1}" cy="{:.1}" r="{SVG_NODE_RADIUS}" fill="white" stroke="{stroke}" stroke-width="2"/>"#,
+                p.x, p.y
+            )
+            .unwrap();
+            writeln!(
+                out,
+                r#"<text x="{:.1}" y="{:.1}" font-family="monospace" font-size="11" text-anchor="middle">{}</text>"#,
+                p.x,
+                p.y + 4.0,
+                node.label
+            )
+            .unwrap();
+            let caption = match node.kind {
+                NodeKind::Input => None,
+                NodeKind::Hidden => Some(format!("{} {:.3}", node.function.name(), node.bias)),
+                NodeKind::Output => Some(node.function.name().to_string()),
+            };
+            if let Some(caption) = caption {
+                writeln!(
+                    out,
+                    r#"<text x="{:.1}" y="{:.1}" font-family="monospace" font-size="9" text-anchor="middle">{caption}</text>"#,
+                    p.x,
+                    p.y + SVG_NODE_RADIUS + 11.0,
+                )
+                .unwrap();
+            }
+        }
+        out.push_str("</svg>\n");
+        out
+    }
+}
+
+fn edge_color(weight: f32) -> &'static str {
+    if weight >= 0.0 {
+        "blue"
+    } else {
+        "red"
+    }
+}
+
+fn edge_width(weight: f32) -> f32 {
+    (0.5 + weight.abs()).min(4.0)
+}
+
+/// Longest path from any input to each node, given the DAG's `edges` in topological order of
+/// their sources. Outputs all share the column after the deepest other node.
+pub(super) fn depths(kinds: &[NodeKind], edges: &[GraphEdge]) -> Vec<usize> {
+    let mut depths: Vec<usize> = kinds
+        .iter()
+        .map(|&kind| (kind != NodeKind::Input) as usize)
+        .collect();
+    for edge in edges {
+        depths[edge.target] = depths[edge.target].max(depths[edge.source] + 1);
+    }
+    let output_depth = kinds
+        .iter()
+        .zip(&depths)
+        .filter(|(&kind, _)| kind != NodeKind::Output)
+        .map(|(_, &depth)| depth + 1)
+        .max()
+        .unwrap_or(1);
+    kinds
+        .iter()
+        .zip(depths)
+        .map(|(&kind, depth)| {
+            if kind == NodeKind::Output {
+                output_depth
+            } else {
+                depth
+            }
+        })
+        .collect()
+}
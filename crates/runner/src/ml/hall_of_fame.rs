@@ -0,0 +1,154 @@
+use super::curriculum::Stage;
+use super::pendulum::{self, EnvConfig, PendulumAgent as CurrentAgent, Scenario};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::f32::consts::PI;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Scenarios of a tournament besides the standard episode: any start, pushed about.
+const TOURNAMENT_STAGE: Stage = Stage {
+    max_angle: PI,
+    steps: 100 * 30,
+    disturbance: 10.0,
+};
+
+/// A directory of the best agents of past runs, one `<name>.agent` file each, kept so that new
+/// runs can be compared against them with `tournament`.
+pub struct HallOfFame {
+    dir: PathBuf,
+}
+
+impl HallOfFame {
+    pub fn open(dir: PathBuf) -> io::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// Saves `agent` under `name`, replacing whatever was there, which lets a run keep updating
+    /// its own entry as it improves.
+    pub fn enter(&self, name: &str, agent: &CurrentAgent) -> io::Result<()> {
+        agent.save(&self.dir.join(format!("{name}.agent")))
+    }
+}
+
+/// Every agent in the hall of fame in `dir` with its name, in name order. Unlike
+/// `HallOfFame::open`, never creates the directory.
+pub fn load_all(dir: &Path) -> io::Result<Vec<(String, CurrentAgent)>> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<_>>()?;
+    paths.retain(|p| p.extension().is_some_and(|e| e == "agent"));
+    paths.sort();
+    paths.iter().map(|path| load_entry(path)).collect()
+}
+
+/// A name for the entry of a run started now, `run-<unix seconds>-<microseconds>`, which also
+/// sorts runs by when they started.
+pub fn run_name() -> String {
+    let since_epoch = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap();
+    format!(
+        "run-{}-{:06}",
+        since_epoch.as_secs(),
+        since_epoch.subsec_micros()
+    )
+}
+
+/// Loads an agent named after its file.
+pub fn load_entry(path: &Path) -> io::Result<(String, CurrentAgent)> {
+    let name = path.file_stem().map_or_else(
+        || path.display().to_string(),
+        |s| s.to_string_lossy().into(),
+    );
+    let agent = CurrentAgent::load(path)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {err}", path.display())))?;
+    Ok((name, agent))
+}
+
+/// The standard episode of `run_simulation` followed by `count` scenarios drawn from `seed`, so
+/// that tournaments with the same seed are directly comparable.
+pub fn tournament_scenarios(count: usize, seed: u64) -> Vec<Scenario> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    std::iter::once(Scenario::default())
+        .chain((0..count).map(|_| TOURNAMENT_STAGE.scenario(&mut rng)))
+        .collect()
+}
+
+/// How one agent did in a tournament.
+#[derive(Clone, Debug)]
+pub struct Standing {
+    pub name: String,
+    /// Score on the standard episode, the first scenario.
+    pub standard: f32,
+    pub mean: f32,
+    pub worst: f32,
+}
+
+/// Evaluates every entry on every scenario and returns the standings from the highest mean
+/// score to the lowest.
+pub fn tournament(
+    entries: Vec<(String, CurrentAgent)>,
+    scenarios: &[Scenario],
+    env: &EnvConfig,
+) -> Vec<Standing> {
+    use rayon::prelude::*;
+    let mut standings: Vec<Standing> = entries
+        .into_par_iter()
+        .map(|(name, mut agent)| {
+            let scores: Vec<f32> = scenarios
+                .iter()
+                .map(|s| pendulum::evaluate_scenario(&mut agent, env, s).score)
+                .collect();
+            Standing {
+                name,
+                standard: scores[0],
+                mean: scores.iter().sum::<f32>() / scores.len() as f32,
+                worst: scores.iter().copied().fold(f32::MAX, f32::min),
+            }
+        })
+        .collect();
+    standings.sort_by(|a, b| b.mean.partial_cmp(&a.mean).unwrap());
+    standings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tournaments_with_the_same_seed_agree() {
+        let mut mutated = CurrentAgent::new();
+        for _ in 0..20 {
+            mutated.mutate();
+        }
+        let entries = vec![
+            ("blank".to_string(), CurrentAgent::new()),
+            ("mutated".to_string(), mutated),
+        ];
+        let env = EnvConfig::realistic();
+        let standings = |seed| {
+            tournament(entries.clone(), &tournament_scenarios(2, seed), &env)
+                .into_iter()
+                .map(|s| (s.name, s.standard, s.mean, s.worst))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(standings(7), standings(7));
+    }
+
+    #[test]
+    fn only_agent_files_are_loaded() {
+        let dir = std::env::temp_dir().join(format!("hall-of-fame-{}", std::process::id()));
+        let hall = HallOfFame::open(dir.clone()).unwrap();
+        hall.enter("second", &CurrentAgent::new()).unwrap();
+        hall.enter("first", &CurrentAgent::new()).unwrap();
+        std::fs::write(dir.join("notes.txt"), "not an agent").unwrap();
+        std::fs::create_dir(dir.join("old")).unwrap();
+        let entries = load_all(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<String> = entries.unwrap().into_iter().map(|e| e.0).collect();
+        assert_eq!(names, ["first", "second"]);
+    }
+}
@@ -0,0 +1,236 @@
+use super::activation::Activation;
+use super::metrics::GenerationStats;
+use super::pendulum::{self, EnvConfig, PendulumAgent as CurrentAgent};
+use super::selection::SelectionConfig;
+use super::{reproduce, Ml};
+use std::collections::BTreeMap;
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug)]
+pub struct IslandConfig {
+    pub islands: usize,
+    /// Agents on every island.
+    pub population: usize,
+    /// Generations between migrations, or 0 to keep the islands apart.
+    pub migration_interval: usize,
+    /// Best agents every island sends to the next one in the ring at each migration, replacing
+    /// its worst.
+    pub migrants: usize,
+}
+
+impl Default for IslandConfig {
+    fn default() -> Self {
+        Self {
+            islands: 4,
+            population: 10,
+            migration_interval: 10,
+            migrants: 1,
+        }
+    }
+}
+
+/// One evaluated generation of one island.
+struct IslandReport {
+    generation: usize,
+    scores_and_agents: Vec<(f32, CurrentAgent)>,
+    evaluation_time: Duration,
+}
+
+impl Ml {
+    /// Evolves separate populations on their own threads, each selecting like `selection` and
+    /// sending copies of its elites around a ring every `config.migration_interval` generations.
+    /// Once every island has finished a generation, its statistics are recorded across all
+    /// islands and the best agent among them is considered as a champion. Reports are bounded so
+    /// that pausing training holds the islands back too.
+    pub fn run_islands(&mut self, config: &IslandConfig, generations: Option<usize>) {
+        let (report_tx, report_rx) = mpsc::sync_channel(config.islands);
+
+        std::thread::scope(|scope| {
+            for (migration_tx, migration_rx) in migration_ring(config.islands) {
+                let island = Island {
+                    env: self.env.clone(),
+                    config: config.clone(),
+                    selection: self.parent_selection.clone(),
+                    output_squash: self.output_squash,
+                    migration_tx,
+                    migration_rx,
+                    report_tx: report_tx.clone(),
+                };
+                let first_generation = self.generation;
+                scope.spawn(move || island.run(first_generation, generations));
+            }
+            drop(report_tx);
+
+            let mut pending: BTreeMap<usize, Vec<IslandReport>> = BTreeMap::new();
+            // Dropping the receiver when training stops makes every island stop too.
+            for report in report_rx {
+                if !self.keep_going(None) {
+                    break;
+                }
+                let generation = report.generation;
+                let reports = pending.entry(generation).or_default();
+                reports.push(report);
+                if reports.len() == config.islands {
+                    let reports = pending.remove(&generation).unwrap();
+                    self.record_islands(generation, reports);
+                }
+            }
+        });
+    }
+
+    fn record_islands(&mut self, generation: usize, reports: Vec<IslandReport>) {
+        let evaluation_time = reports.iter().map(|r| r.evaluation_time).max().unwrap();
+        let scores_and_agents: Vec<(f32, CurrentAgent)> = reports
+            .into_iter()
+            .flat_map(|r| r.scores_and_agents)
+            .collect();
+        self.generation = generation;
+        self.record_stats(GenerationStats::new(
+            self.generation,
+            &scores_and_agents,
+            evaluation_time,
+        ));
+        let (best_score, best_agent) = scores_and_agents
+            .iter()
+            .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+            .unwrap();
+        self.consider_champion(best_agent, *best_score);
+    }
+}
+
+type Migrants = Vec<(f32, CurrentAgent)>;
+
+/// Migration channel ends for every island, where island `i` sends to the receiver of island
+/// `i + 1` and the last one to the first.
+fn migration_ring(islands: usize) -> Vec<(Sender<Migrants>, Receiver<Migrants>)> {
+    let (mut txs, rxs): (Vec<Sender<_>>, Vec<Receiver<_>>) =
+        (0..islands).map(|_| mpsc::channel()).unzip();
+    txs.rotate_left(1);
+    txs.into_iter().zip(rxs).collect()
+}
+
+struct Island {
+    env: EnvConfig,
+    config: IslandConfig,
+    selection: SelectionConfig,
+    output_squash: Option<Activation>,
+    migration_tx: Sender<Migrants>,
+    migration_rx: Receiver<Migrants>,
+    report_tx: SyncSender<IslandReport>,
+}
+
+impl Island {
+    /// Evaluates one agent at a time, since the islands themselves already keep every core
+    /// busy.
+    fn run(self, mut generation: usize, generations: Option<usize>) {
+        let mut agents: Vec<CurrentAgent> = (0..self.config.population)
+            .map(|_| CurrentAgent::with_squash(self.output_squash))
+            .collect();
+        while generations.map_or(true, |n| generation < n) {
+            let start = Instant::now();
+            let mut scores_and_agents: Vec<(f32, CurrentAgent)> = agents
+                .into_iter()
+                .map(|mut agent| (pendulum::run_simulation(&mut agent, &self.env), agent))
+                .collect();
+            let evaluation_time = start.elapsed();
+            scores_and_agents.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+            generation += 1;
+
+            let report = IslandReport {
+                generation,
+                scores_and_agents: scores_and_agents.clone(),
+                evaluation_time,
+            };
+            if self.report_tx.send(report).is_err() {
+                return;
+            }
+            self.migrate(generation, &mut scores_and_agents);
+            agents = reproduce(scores_and_agents, &self.selection);
+        }
+    }
+
+    /// Sends elites on migration generations, and lets whichever migrants have arrived replace
+    /// the worst agents. A neighbour that has already stopped simply misses out.
+    fn migrate(&self, generation: usize, scores_and_agents: &mut [(f32, CurrentAgent)]) {
+        if generation.checked_rem(self.config.migration_interval) == Some(0) {
+            let elites = scores_and_agents
+                .iter()
+                .rev()
+                .take(self.config.migrants)
+                .cloned()
+                .collect();
+            let _ = self.migration_tx.send(elites);
+        }
+        let mut replaced = 0;
+        for migrants in self.migration_rx.try_iter() {
+            for migrant in migrants {
+                if replaced < scores_and_agents.len() {
+                    scores_and_agents[replaced] = migrant;
+                    replaced += 1;
+                }
+            }
+        }
+        if replaced > 0 {
+            scores_and_agents.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Agents told apart by their scores alone.
+    fn scored(scores: &[f32]) -> Vec<(f32, CurrentAgent)> {
+        scores.iter().map(|&s| (s, CurrentAgent::new())).collect()
+    }
+
+    fn scores(scores_and_agents: &[(f32, CurrentAgent)]) -> Vec<f32> {
+        scores_and_agents.iter().map(|x| x.0).collect()
+    }
+
+    #[test]
+    fn migrants_replace_the_worst_agents() {
+        let (report_tx, _report_rx) = mpsc::sync_channel(1);
+        let (migration_tx, elites_rx) = mpsc::channel();
+        let (migrants_tx, migration_rx) = mpsc::channel();
+        let island = Island {
+            env: EnvConfig::default(),
+            config: IslandConfig {
+                migration_interval: 5,
+                migrants: 2,
+                ..IslandConfig::default()
+            },
+            selection: SelectionConfig::default(),
+            output_squash: None,
+            migration_tx,
+            migration_rx,
+            report_tx,
+        };
+        migrants_tx.send(scored(&[2.5, 9.0])).unwrap();
+
+        let mut population = scored(&[1.0, 2.0, 3.0, 4.0, 5.0]);
+        island.migrate(5, &mut population);
+        assert_eq!(scores(&population), [2.5, 3.0, 4.0, 5.0, 9.0]);
+        // The elites leave before the migrants arrive.
+        assert_eq!(scores(&elites_rx.try_recv().unwrap()), [5.0, 4.0]);
+
+        island.migrate(6, &mut population);
+        assert!(elites_rx.try_recv().is_err());
+        assert_eq!(scores(&population), [2.5, 3.0, 4.0, 5.0, 9.0]);
+    }
+
+    #[test]
+    fn migrants_travel_around_the_ring() {
+        let ring = migration_ring(3);
+        for (i, (tx, _)) in ring.iter().enumerate() {
+            tx.send(scored(&[i as f32])).unwrap();
+        }
+        let arrived: Vec<Vec<f32>> = ring
+            .iter()
+            .map(|(_, rx)| scores(&rx.try_recv().unwrap()))
+            .collect();
+        assert_eq!(arrived, [[2.0], [0.0], [1.0]]);
+    }
+}
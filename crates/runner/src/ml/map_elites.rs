@@ -0,0 +1,152 @@
+use super::metrics::GenerationStats;
+use super::pendulum::{self, Behaviour, PendulumAgent as CurrentAgent};
+use super::Ml;
+use rand::prelude::*;
+use std::time::Instant;
+
+#[derive(Clone, Debug)]
+pub struct MapElitesConfig {
+    /// Cells along each behaviour descriptor.
+    pub resolution: usize,
+    /// Mutated elites evaluated per generation.
+    pub batch: usize,
+}
+
+impl Default for MapElitesConfig {
+    fn default() -> Self {
+        Self {
+            resolution: 10,
+            batch: 50,
+        }
+    }
+}
+
+/// The best agent found so far for every cell of a grid over `Behaviour`: the highest the bob
+/// got along one axis, the mean cart position along the other.
+struct Archive {
+    resolution: usize,
+    cells: Vec<Option<(f32, CurrentAgent)>>,
+}
+
+impl Archive {
+    fn new(resolution: usize) -> Self {
+        Self {
+            resolution,
+            cells: vec![None; resolution * resolution],
+        }
+    }
+
+    fn cell(&self, behaviour: &Behaviour) -> usize {
+        let bin = |x: f32| ((x * self.resolution as f32) as usize).min(self.resolution - 1);
+        let height = bin(((behaviour.max_height + 1.0) / 2.0).clamp(0.0, 1.0));
+        let cart_x = bin((behaviour.mean_cart_x + 0.5).clamp(0.0, 1.0));
+        height * self.resolution + cart_x
+    }
+
+    /// Keeps `agent` if its cell is empty or holds a lower score.
+    fn insert(&mut self, score: f32, behaviour: &Behaviour, agent: &CurrentAgent) {
+        let cell = self.cell(behaviour);
+        if self.cells[cell]
+            .as_ref()
+            .map_or(true, |elite| score > elite.0)
+        {
+            self.cells[cell] = Some((score, agent.clone()));
+        }
+    }
+
+    fn random_elite(&self, rng: &mut impl Rng) -> Option<&CurrentAgent> {
+        let elites: Vec<&CurrentAgent> = self.cells.iter().flatten().map(|x| &x.1).collect();
+        elites.choose(rng).copied()
+    }
+
+    fn occupied(&self) -> usize {
+        self.cells.iter().flatten().count()
+    }
+}
+
+impl Ml {
+    /// Fills a grid of behaviours with the best agent for each, breeding from elites picked
+    /// uniformly so that every niche keeps being explored however poorly it scores.
+    pub fn run_map_elites(&mut self, config: &MapElitesConfig, generations: Option<usize>) {
+        let mut archive = Archive::new(config.resolution);
+        let mut rng = thread_rng();
+        while self.keep_going(generations) {
+            let agents: Vec<CurrentAgent> = (0..config.batch)
+                .map(|_| match archive.random_elite(&mut rng) {
+                    Some(elite) => {
+                        let mut agent = elite.clone();
+                        agent.mutate();
+                        agent
+                    }
+                    None => self.new_agent(),
+                })
+                .collect();
+            self.map_elites_generation(agents, &mut archive);
+        }
+    }
+
+    fn map_elites_generation(&mut self, agents: Vec<CurrentAgent>, archive: &mut Archive) {
+        use rayon::prelude::*;
+        let start = Instant::now();
+        let (behaviours, scores_and_agents): (Vec<Behaviour>, Vec<(f32, CurrentAgent)>) = agents
+            .into_par_iter()
+            .map(|mut agent| {
+                let evaluation = pendulum::evaluate(&mut agent, &self.env);
+                (evaluation.behaviour, (evaluation.score, agent))
+            })
+            .unzip();
+        let evaluation_time = start.elapsed();
+
+        for (behaviour, (score, agent)) in behaviours.iter().zip(&scores_and_agents) {
+            archive.insert(*score, behaviour, agent);
+        }
+
+        self.generation += 1;
+        self.record_stats(GenerationStats {
+            coverage: Some(archive.occupied() as f32 / archive.cells.len() as f32),
+            ..GenerationStats::new(self.generation, &scores_and_agents, evaluation_time)
+        });
+        let (best_score, best_agent) = scores_and_agents
+            .iter()
+            .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+            .unwrap();
+        self.consider_champion(best_agent, *best_score);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn behaviour(max_height: f32, mean_cart_x: f32) -> Behaviour {
+        Behaviour {
+            max_height,
+            mean_cart_x,
+        }
+    }
+
+    #[test]
+    fn extreme_behaviours_fall_into_the_end_cells() {
+        let archive = Archive::new(4);
+        assert_eq!(archive.cell(&behaviour(-1.0, -0.5)), 0);
+        assert_eq!(archive.cell(&behaviour(-3.0, -2.0)), 0);
+        assert_eq!(archive.cell(&behaviour(1.0, 0.5)), 15);
+        assert_eq!(archive.cell(&behaviour(3.0, 2.0)), 15);
+        assert_eq!(archive.cell(&behaviour(1.0, -0.5)), 12);
+        assert_eq!(archive.cell(&behaviour(-0.4, 0.1)), 6);
+    }
+
+    #[test]
+    fn higher_scores_replace_elites() {
+        let mut archive = Archive::new(4);
+        let agent = CurrentAgent::new();
+        let score = |archive: &Archive| archive.cells[15].as_ref().map(|elite| elite.0);
+        archive.insert(1.0, &behaviour(0.9, 0.4), &agent);
+        archive.insert(0.5, &behaviour(1.0, 0.5), &agent);
+        assert_eq!(score(&archive), Some(1.0));
+        archive.insert(2.0, &behaviour(0.8, 0.3), &agent);
+        assert_eq!(score(&archive), Some(2.0));
+        archive.insert(3.0, &behaviour(-1.0, -0.5), &agent);
+        assert_eq!(archive.occupied(), 2);
+    }
+}
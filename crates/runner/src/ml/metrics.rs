@@ -0,0 +1,154 @@
+use super::{Agent, Inputs, Outputs};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// Agents closer than this are counted as the same species.
+const SPECIES_THRESHOLD: f32 = 3.0;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SizeStats {
+    pub min: usize,
+    pub mean: f32,
+    pub max: usize,
+}
+
+impl SizeStats {
+    fn new(sizes: impl Iterator<Item = usize> + Clone) -> Self {
+        let count = sizes.clone().count().max(1);
+        Self {
+            min: sizes.clone().min().unwrap_or(0),
+            mean: sizes.clone().sum::<usize>() as f32 / count as f32,
+            max: sizes.max().unwrap_or(0),
+        }
+    }
+
+    fn to_json(self) -> String {
+        format!(
+            r#"{{"min":{},"mean":{},"max":{}}}"#,
+            self.min, self.mean, self.max
+        )
+    }
+}
+
+/// Summary of one evaluated generation.
+#[derive(Clone, Debug)]
+pub struct GenerationStats {
+    pub generation: usize,
+    pub best: f32,
+    pub mean: f32,
+    pub median: f32,
+    pub worst: f32,
+    pub nodes: SizeStats,
+    pub edges: SizeStats,
+    pub species: usize,
+    /// Simulations run per second of wall-clock time during evaluation.
+    pub throughput: f32,
+    /// Fraction of the MAP-Elites archive's cells holding an elite, for that search only.
+    pub coverage: Option<f32>,
+    /// Curriculum stage the generation trained in, counting from 1, for that search only.
+    pub stage: Option<usize>,
+}
+
+impl GenerationStats {
+    pub fn new<I: Inputs, O: Outputs>(
+        generation: usize,
+        scores_and_agents: &[(f32, Agent<I, O>)],
+        evaluation_time: Duration,
+    ) -> Self {
+        let scores: Vec<f32> = scores_and_agents.iter().map(|x| x.0).collect();
+        let agents = scores_and_agents.iter().map(|x| &x.1);
+        Self {
+            nodes: SizeStats::new(agents.clone().map(Agent::node_count)),
+            edges: SizeStats::new(agents.clone().map(Agent::edge_count)),
+            species: count_species(agents),
+            ..Self::from_scores(generation, &scores, evaluation_time)
+        }
+    }
+
+    /// Statistics of a generation of policies that aren't agents, like those of gradient-based
+    /// training, which leaves the network sizes at zero and counts a single species.
+    pub fn from_scores(generation: usize, scores: &[f32], evaluation_time: Duration) -> Self {
+        let mut scores = scores.to_vec();
+        scores.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let count = scores.len();
+        let median = (scores[(count - 1) / 2] + scores[count / 2]) / 2.0;
+        Self {
+            generation,
+            best: scores[count - 1],
+            mean: scores.iter().sum::<f32>() / count as f32,
+            median,
+            worst: scores[0],
+            nodes: SizeStats::default(),
+            edges: SizeStats::default(),
+            species: 1,
+            throughput: count as f32 / evaluation_time.as_secs_f32().max(f32::EPSILON),
+            coverage: None,
+            stage: None,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut extra = String::new();
+        if let Some(coverage) = self.coverage {
+            extra += &format!(r#","coverage":{coverage}"#);
+        }
+        if let Some(stage) = self.stage {
+            extra += &format!(r#","stage":{stage}"#);
+        }
+        format!(
+            concat!(
+                r#"{{"generation":{},"best":{},"mean":{},"median":{},"worst":{},"#,
+                r#""nodes":{},"edges":{},"species":{},"throughput":{}{}}}"#
+            ),
+            self.generation,
+            self.best,
+            self.mean,
+            self.median,
+            self.worst,
+            self.nodes.to_json(),
+            self.edges.to_json(),
+            self.species,
+            self.throughput,
+            extra,
+        )
+    }
+}
+
+/// Greedily clusters agents around the first member of each species.
+fn count_species<'a, I: Inputs + 'a, O: Outputs + 'a>(
+    agents: impl Iterator<Item = &'a Agent<I, O>>,
+) -> usize {
+    let mut representatives: Vec<&Agent<I, O>> = Vec::new();
+    for agent in agents {
+        if !representatives
+            .iter()
+            .any(|r| r.distance(agent) < SPECIES_THRESHOLD)
+        {
+            representatives.push(agent);
+        }
+    }
+    representatives.len()
+}
+
+/// Appends one JSON object per generation to a file.
+pub struct MetricsLog {
+    file: BufWriter<File>,
+}
+
+impl MetricsLog {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Ok(Self {
+            file: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    pub fn write(&mut self, stats: &GenerationStats) -> io::Result<()> {
+        writeln!(self.file, "{}", stats.to_json())?;
+        self.file.flush()
+    }
+}
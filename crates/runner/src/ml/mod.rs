@@ -1,24 +1,66 @@
+use crate::trajectory::Trajectory;
+use activation::Activation;
 use daggy::petgraph::stable_graph::{edge_index, node_index};
 use daggy::Walker;
-use pendulum::PendulumAgent as CurrentAgent;
-use rand::distributions::{Uniform, WeightedError, WeightedIndex};
+use graph::{AgentGraph, GraphEdge, GraphNode, NodeKind};
+use hall_of_fame::HallOfFame;
+use metrics::{GenerationStats, MetricsLog};
+use nsga2::ParetoFront;
+use pendulum::{EnvConfig, PendulumAgent as CurrentAgent};
 use rand::prelude::*;
+use selection::SelectionConfig;
+use std::collections::{HashMap, HashSet};
 use std::marker::PhantomData;
-use std::sync::mpsc::Sender;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, Sender};
+use std::time::Instant;
 
+pub mod activation;
+pub mod autodiff;
+pub mod cmaes;
+pub mod curriculum;
+pub mod genome;
+pub mod graph;
+pub mod hall_of_fame;
+pub mod islands;
+pub mod map_elites;
+pub mod metrics;
+pub mod novelty;
+pub mod nsga2;
 pub mod pendulum;
+pub mod prune;
+pub mod qlearning;
+pub mod rl;
+pub mod selection;
+pub mod training;
+
+/// Source of hidden node ids, shared by all agents so that two nodes only have the same id if
+/// one was copied from the other.
+static NEXT_HIDDEN_ID: AtomicUsize = AtomicUsize::new(0);
 
 #[derive(Clone, Debug)]
 struct Node {
+    /// Unlike the index, stays the same when other nodes are removed, so that nodes of two
+    /// agents can be matched up. Inputs and outputs use their index.
+    id: usize,
     value: f32,
     bias: f32,
+    /// Applied by hidden nodes, or the squashing of outputs. Inputs pass their value through.
+    function: Activation,
+    /// Value passed on to the children during the last `choose`, or returned for outputs. It is
+    /// the agent's state, which recurrent edges read in the next `choose`.
+    activation: f32,
 }
 
 impl Node {
-    fn random() -> Self {
+    fn random(id: usize) -> Self {
         Self {
+            id,
             value: 0.0,
             bias: thread_rng().gen_range(-1.0..=1.0),
+            function: Activation::random(),
+            activation: 0.0,
         }
     }
 }
@@ -38,9 +80,19 @@ impl Edge {
     }
 }
 
+/// Connection from a node's activation in the previous `choose` to another node. Unlike the
+/// edges in the DAG, these may form cycles.
+#[derive(Clone, Copy, Debug)]
+struct RecurrentEdge {
+    source: usize,
+    target: usize,
+    edge: Edge,
+}
+
 #[derive(Clone)]
 pub struct Agent<I: Inputs, O: Outputs> {
     dag: daggy::Dag<Node, Edge>,
+    recurrent: Vec<RecurrentEdge>,
     _inputs: PhantomData<I>,
     _outputs: PhantomData<O>,
 }
@@ -48,22 +100,113 @@ pub struct Agent<I: Inputs, O: Outputs> {
 impl<I: Inputs, O: Outputs> Agent<I, O> {
     fn new() -> Self {
         let mut dag = daggy::Dag::new();
-        for _ in 0..I::COUNT {
-            dag.add_node(Node::random());
+        for id in 0..I::COUNT {
+            dag.add_node(Node::random(id));
         }
-        for _ in 0..O::COUNT {
-            dag.add_node(Node::random());
+        for index in 0..O::COUNT {
+            dag.add_node(Node {
+                function: O::squash(index),
+                ..Node::random(I::COUNT + index)
+            });
         }
         Self {
             dag,
+            recurrent: Vec::new(),
             _inputs: PhantomData,
             _outputs: PhantomData,
         }
     }
 
+    /// Random agent whose outputs are all squashed with `squash`, or with `Outputs::squash` if
+    /// it is `None`.
+    pub fn with_squash(squash: Option<Activation>) -> Self {
+        let mut agent = Self::new();
+        if let Some(squash) = squash {
+            for index in 0..O::COUNT {
+                agent.set_squash(index, squash);
+            }
+        }
+        agent
+    }
+
+    /// Changes the squashing of output `index`, which mutation leaves alone.
+    pub fn set_squash(&mut self, index: usize, squash: Activation) {
+        self.dag
+            .node_weight_mut(node_index(I::COUNT + index))
+            .unwrap()
+            .function = squash;
+    }
+
+    /// Id for a new hidden node, past those of the inputs and outputs.
+    fn hidden_id() -> usize {
+        I::COUNT + O::COUNT + NEXT_HIDDEN_ID.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Fixed-topology policy with `hidden` tanh nodes between every input and every output, or
+    /// a linear one connecting the inputs straight to the outputs if `hidden` is zero.
+    pub fn layered(hidden: usize) -> Self {
+        let mut agent = Self::new();
+        let inputs = (0..I::COUNT).map(node_index);
+        let outputs = (I::COUNT..I::COUNT + O::COUNT).map(node_index);
+        if hidden == 0 {
+            for input in inputs {
+                for output in outputs.clone() {
+                    agent.dag.add_edge(input, output, Edge::random()).unwrap();
+                }
+            }
+            return agent;
+        }
+        for _ in 0..hidden {
+            let node = agent.dag.add_node(Node {
+                function: Activation::Tanh,
+                ..Node::random(Self::hidden_id())
+            });
+            for input in inputs.clone() {
+                agent.dag.add_edge(input, node, Edge::random()).unwrap();
+            }
+            for output in outputs.clone() {
+                agent.dag.add_edge(node, output, Edge::random()).unwrap();
+            }
+        }
+        agent
+    }
+
+    /// Every tunable number: edge weights, recurrent edge weights, then the biases of inputs and
+    /// hidden nodes. Outputs ignore their bias.
+    pub fn parameters(&self) -> Vec<f32> {
+        let edges = self.dag.raw_edges().iter().map(|e| e.weight.weight);
+        let recurrent = self.recurrent.iter().map(|r| r.edge.weight);
+        let biases = self
+            .dag
+            .raw_nodes()
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| Self::node_kind(*i) != NodeKind::Output)
+            .map(|(_, n)| n.weight.bias);
+        edges.chain(recurrent).chain(biases).collect()
+    }
+
+    /// Inverse of `parameters`, keeping the topology.
+    pub fn set_parameters(&mut self, parameters: &[f32]) {
+        let mut parameters = parameters.iter().copied();
+        for edge in self.dag.edge_weights_mut() {
+            edge.weight = parameters.next().unwrap();
+        }
+        for r in &mut self.recurrent {
+            r.edge.weight = parameters.next().unwrap();
+        }
+        for (i, node) in self.dag.node_weights_mut().enumerate() {
+            if Self::node_kind(i) != NodeKind::Output {
+                node.bias = parameters.next().unwrap();
+            }
+        }
+        debug_assert!(parameters.next().is_none());
+    }
+
     fn mutate(&mut self) {
         let mut rng = thread_rng();
-        for edge in self.dag.edge_weights_mut() {
+        let recurrent_edges = self.recurrent.iter_mut().map(|r| &mut r.edge);
+        for edge in self.dag.edge_weights_mut().chain(recurrent_edges) {
             if rng.gen_bool(0.2) {
                 if rng.gen_bool(0.2) {
                     *edge = Edge::random();
@@ -74,16 +217,23 @@ impl<I: Inputs, O: Outputs> Agent<I, O> {
                 }
             }
         }
-        for node in self.dag.node_weights_mut() {
+        for (i, node) in self.dag.node_weights_mut().enumerate() {
+            if Self::node_kind(i) == NodeKind::Output {
+                // Outputs ignore their bias and keep their squashing.
+                continue;
+            }
             if rng.gen_bool(0.2) {
                 if rng.gen_bool(0.2) {
-                    *node = Node::random();
+                    *node = Node::random(node.id);
                 } else if rng.gen_bool(0.25) {
                     node.bias += rng.gen_range(-1.0..=1.0);
                 } else {
                     node.bias += 0.01 * rng.gen_range(-1.0..=1.0);
                 }
             }
+            if rng.gen_bool(0.05) {
+                node.function = Activation::random();
+            }
         }
         if self.dag.node_count() < 30 && rng.gen_bool(0.25) {
             self.new_node();
@@ -91,6 +241,12 @@ impl<I: Inputs, O: Outputs> Agent<I, O> {
         if rng.gen_bool(0.25) {
             self.new_connection();
         }
+        if rng.gen_bool(0.1) {
+            self.remove_connection();
+        }
+        if rng.gen_bool(0.05) {
+            self.remove_random_node();
+        }
     }
 
     fn new_connection(&mut self) {
@@ -103,10 +259,33 @@ impl<I: Inputs, O: Outputs> Agent<I, O> {
         let source_node = node_index(i);
         let i = rng.gen_range(I::COUNT..count);
         let target_node = node_index(i);
-        if self.dag.find_edge(source_node, target_node).is_none() {
+        if self.dag.find_edge(source_node, target_node).is_some() {
+            return;
+        }
+        // `daggy` skips its cycle check for sources without parents, which deletions create.
+        let result = if source_node == target_node {
+            Err(Edge::random())
+        } else {
             self.dag
                 .add_edge(source_node, target_node, Edge::random())
-                .ok();
+                .map_err(|err| err.0)
+        };
+        if let Err(edge) = result {
+            self.add_recurrent(source_node.index(), target_node.index(), edge);
+        }
+    }
+
+    fn add_recurrent(&mut self, source: usize, target: usize, edge: Edge) {
+        if !self
+            .recurrent
+            .iter()
+            .any(|r| r.source == source && r.target == target)
+        {
+            self.recurrent.push(RecurrentEdge {
+                source,
+                target,
+                edge,
+            });
         }
     }
 
@@ -121,27 +300,143 @@ impl<I: Inputs, O: Outputs> Agent<I, O> {
             (edge.weight, edge.source(), edge.target())
         };
         self.dag.remove_edge(edge_index(i)).unwrap();
-        let (_, node_index) = self.dag.add_child(parent, edge, Node::random());
+        let (_, node_index) = self
+            .dag
+            .add_child(parent, edge, Node::random(Self::hidden_id()));
         self.dag
             .add_edge(node_index, target, Edge::new(1.0))
             .unwrap();
     }
 
+    fn remove_connection(&mut self) {
+        let count = self.edge_count();
+        if count > 0 {
+            let i = thread_rng().gen_range(0..count);
+            if i < self.dag.edge_count() {
+                self.dag.remove_edge(edge_index(i));
+            } else {
+                self.recurrent.swap_remove(i - self.dag.edge_count());
+            }
+        }
+    }
+
+    fn remove_random_node(&mut self) {
+        let first_hidden = I::COUNT + O::COUNT;
+        let count = self.dag.node_count();
+        if count > first_hidden {
+            self.remove_hidden_node(thread_rng().gen_range(first_hidden..count));
+        }
+    }
+
+    /// Removes a hidden node with its edges. The last node takes over its index, so inputs and
+    /// outputs keep theirs.
+    fn remove_hidden_node(&mut self, index: usize) {
+        debug_assert!(index >= I::COUNT + O::COUNT);
+        let last = self.dag.node_count() - 1;
+        self.recurrent
+            .retain(|r| r.source != index && r.target != index);
+        for r in &mut self.recurrent {
+            if r.source == last {
+                r.source = index;
+            }
+            if r.target == last {
+                r.target = index;
+            }
+        }
+        self.dag.remove_node(node_index(index));
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.dag.node_count()
+    }
+
+    /// Edges in the DAG and recurrent ones.
+    pub fn edge_count(&self) -> usize {
+        self.dag.edge_count() + self.recurrent.len()
+    }
+
+    /// Forgets everything remembered from previous `choose` calls, as at the start of an episode.
+    pub fn reset_state(&mut self) {
+        for node in self.dag.node_weights_mut() {
+            node.value = 0.0;
+            node.activation = 0.0;
+        }
+    }
+
+    /// Compatibility distance used to group agents into species: the number of edges and nodes
+    /// only one of them has, plus the mean weight difference of the edges they share. Nodes are
+    /// matched by id, since removing a node moves another one to its index.
+    fn distance(&self, other: &Self) -> f32 {
+        let ours = self.edges_by_id();
+        let theirs = other.edges_by_id();
+        let mut matching = 0;
+        let mut weight_difference = 0.0;
+        for (key, weight) in &ours {
+            if let Some(other_weight) = theirs.get(key) {
+                matching += 1;
+                weight_difference += (weight - other_weight).abs();
+            }
+        }
+        let disjoint = ours.len() + theirs.len() - 2 * matching;
+        let nodes = self
+            .node_ids()
+            .symmetric_difference(&other.node_ids())
+            .count();
+        let weights = if matching > 0 {
+            weight_difference / matching as f32
+        } else {
+            0.0
+        };
+        (disjoint + nodes) as f32 + weights
+    }
+
+    fn node_ids(&self) -> HashSet<usize> {
+        self.dag.raw_nodes().iter().map(|n| n.weight.id).collect()
+    }
+
+    /// Weights keyed by whether the edge is recurrent and the ids of its source and target.
+    fn edges_by_id(&self) -> HashMap<(bool, usize, usize), f32> {
+        let id = |index: usize| self.dag.node_weight(node_index(index)).unwrap().id;
+        let dag = self.dag.raw_edges().iter().map(|e| {
+            let key = (false, id(e.source().index()), id(e.target().index()));
+            (key, e.weight.weight)
+        });
+        let recurrent = self
+            .recurrent
+            .iter()
+            .map(|r| ((true, id(r.source), id(r.target)), r.edge.weight));
+        dag.chain(recurrent).collect()
+    }
+
     pub fn choose(&mut self, inputs: I) -> O {
         for node in self.dag.node_weights_mut() {
             node.value = 0.0;
         }
+        for r in &self.recurrent {
+            let previous = self
+                .dag
+                .node_weight(node_index(r.source))
+                .unwrap()
+                .activation;
+            self.dag
+                .node_weight_mut(node_index(r.target))
+                .unwrap()
+                .value += previous * r.edge.weight;
+        }
         let sorted_nodes = daggy::petgraph::algo::toposort(self.dag.graph(), None).unwrap();
 
         for source_node in sorted_nodes {
             let source_neuron_value = {
+                let index = source_node.index();
                 let source_neuron = self.dag.node_weight_mut(source_node).unwrap();
-                source_neuron.bias
-                    + (if source_node.index() < I::COUNT {
-                        inputs.get(source_node.index())
-                    } else {
-                        source_neuron.value.tanh()
-                    })
+                source_neuron.activation = match Self::node_kind(index) {
+                    NodeKind::Input => source_neuron.bias + inputs.get(index),
+                    NodeKind::Hidden => {
+                        source_neuron.bias + source_neuron.function.apply(source_neuron.value)
+                    }
+                    NodeKind::Output => source_neuron.function.apply(source_neuron.value),
+                };
+                source_neuron.activation
             };
 
             let mut children = self.dag.children(source_node);
@@ -152,82 +447,343 @@ impl<I: Inputs, O: Outputs> Agent<I, O> {
             }
         }
 
-        let count = self.dag.node_count();
-        O::from_iter(
-            (count - O::COUNT..count).map(|i| self.dag.node_weight(node_index(i)).unwrap().value),
-        )
+        O::from_iter(self.output_values())
+    }
+
+    /// Outputs computed by the last `choose`.
+    fn output_values(&self) -> impl Iterator<Item = f32> + '_ {
+        (I::COUNT..I::COUNT + O::COUNT)
+            .map(|i| self.dag.node_weight(node_index(i)).unwrap().activation)
+    }
+
+    /// Inputs come first, then outputs, then hidden nodes in the order they were added.
+    fn node_kind(index: usize) -> NodeKind {
+        if index < I::COUNT {
+            NodeKind::Input
+        } else if index < I::COUNT + O::COUNT {
+            NodeKind::Output
+        } else {
+            NodeKind::Hidden
+        }
+    }
+
+    pub fn graph(&self) -> AgentGraph {
+        let sorted_nodes = daggy::petgraph::algo::toposort(self.dag.graph(), None).unwrap();
+        let mut edges = Vec::with_capacity(self.dag.edge_count());
+        for source_node in sorted_nodes {
+            let mut children = self.dag.children(source_node);
+            while let Some((edge, target_node)) = children.walk_next(&self.dag) {
+                edges.push(GraphEdge {
+                    source: source_node.index(),
+                    target: target_node.index(),
+                    weight: self.dag.edge_weight(edge).unwrap().weight,
+                    recurrent: false,
+                });
+            }
+        }
+        let kinds: Vec<NodeKind> = (0..self.dag.node_count()).map(Self::node_kind).collect();
+        let depths = graph::depths(&kinds, &edges);
+        edges.extend(self.recurrent.iter().map(|r| GraphEdge {
+            source: r.source,
+            target: r.target,
+            weight: r.edge.weight,
+            recurrent: true,
+        }));
+        let nodes = depths
+            .into_iter()
+            .zip(kinds)
+            .enumerate()
+            .map(|(i, (depth, kind))| {
+                let node = self.dag.node_weight(node_index(i)).unwrap();
+                GraphNode {
+                    label: match kind {
+                        NodeKind::Input => I::name(i).to_string(),
+                        NodeKind::Output => O::name(i - I::COUNT).to_string(),
+                        NodeKind::Hidden => format!("h{i}"),
+                    },
+                    kind,
+                    depth,
+                    bias: node.bias,
+                    function: node.function,
+                    activation: node.activation,
+                }
+            })
+            .collect();
+        AgentGraph { nodes, edges }
     }
 }
 
 pub trait Inputs {
     const COUNT: usize;
     fn get(&self, index: usize) -> f32;
+    fn name(index: usize) -> &'static str;
 }
 
 pub trait Outputs {
     const COUNT: usize;
+    fn name(index: usize) -> &'static str;
+    /// Applied to the weighted sum reaching each output of a new agent, unless configured
+    /// otherwise.
+    fn squash(_index: usize) -> Activation {
+        Activation::Identity
+    }
     fn from_iter<I: Iterator<Item = f32>>(it: I) -> Self;
 }
 
+/// A new best agent, as reported to the viewer.
+#[derive(Clone)]
+pub struct Champion {
+    pub agent: CurrentAgent,
+    pub generation: usize,
+    pub score: f32,
+}
+
+/// What evolution selects agents for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Search {
+    /// Score in `run_simulation`.
+    #[default]
+    Fitness,
+    /// Behaving unlike the agents seen before.
+    Novelty,
+    /// Being the best agent for its kind of behaviour.
+    MapElites,
+    /// Trading off the separate objectives, keeping a Pareto front.
+    Nsga2,
+    /// Score, in separate populations that exchange their best agents now and then.
+    Islands,
+    /// Score, starting from easy scenarios that get harder as the population masters them.
+    Curriculum,
+}
+
+impl Search {
+    /// Whether changing the parent selection while training takes effect. The other searches
+    /// either don't breed from a ranked population or fixed their selection when they started.
+    pub fn follows_selection(self) -> bool {
+        matches!(self, Self::Fitness | Self::Novelty | Self::Curriculum)
+    }
+}
+
+impl std::str::FromStr for Search {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fitness" => Ok(Self::Fitness),
+            "novelty" => Ok(Self::Novelty),
+            "map-elites" => Ok(Self::MapElites),
+            "nsga2" => Ok(Self::Nsga2),
+            "islands" => Ok(Self::Islands),
+            "curriculum" => Ok(Self::Curriculum),
+            _ => Err(format!("unknown search `{s}`")),
+        }
+    }
+}
+
 pub struct Ml {
-    sender: Sender<CurrentAgent>,
+    sender: Sender<Champion>,
+    env: EnvConfig,
     best_score: f32,
+    improvements: usize,
+    generation: usize,
+    trajectory_dir: Option<PathBuf>,
+    champion_dir: Option<PathBuf>,
+    hall_of_fame: Option<(HallOfFame, String)>,
+    metrics_log: Option<MetricsLog>,
+    stats_sender: Option<Sender<GenerationStats>>,
+    front_sender: Option<Sender<ParetoFront>>,
+    parent_selection: SelectionConfig,
+    output_squash: Option<Activation>,
+    best: Option<Champion>,
+    control: Option<Receiver<training::Command>>,
+    paused: bool,
+    stopped: bool,
 }
 
 impl Ml {
-    pub fn new(sender: Sender<CurrentAgent>) -> Self {
+    pub fn new(sender: Sender<Champion>, env: EnvConfig) -> Self {
         Self {
             sender,
+            env,
             best_score: 0.0,
+            improvements: 0,
+            generation: 0,
+            trajectory_dir: None,
+            champion_dir: None,
+            hall_of_fame: None,
+            metrics_log: None,
+            stats_sender: None,
+            front_sender: None,
+            parent_selection: SelectionConfig::default(),
+            output_squash: None,
+            best: None,
+            control: None,
+            paused: false,
+            stopped: false,
         }
     }
 
-    pub fn run_experiment(&mut self) {
-        let mut agents: Vec<CurrentAgent> = (0..10).map(|_| CurrentAgent::new()).collect();
-        loop {
+    /// Writes the statistics of every generation to `log`.
+    pub fn log_metrics(&mut self, log: MetricsLog) {
+        self.metrics_log = Some(log);
+    }
+
+    /// Sends the statistics of every generation to `sender`.
+    pub fn report_stats(&mut self, sender: Sender<GenerationStats>) {
+        self.stats_sender = Some(sender);
+    }
+
+    /// Picks parents with `config` in the searches that breed from a ranked population.
+    pub fn select_with(&mut self, config: SelectionConfig) {
+        self.parent_selection = config;
+    }
+
+    /// Squashes the outputs of new agents with `squash` instead of `Outputs::squash`.
+    pub fn squash_outputs_with(&mut self, squash: Activation) {
+        self.output_squash = Some(squash);
+    }
+
+    fn new_agent(&self) -> CurrentAgent {
+        CurrentAgent::with_squash(self.output_squash)
+    }
+
+    /// Sends the Pareto front of every generation of `run_nsga2` to `sender`.
+    pub fn report_front(&mut self, sender: Sender<ParetoFront>) {
+        self.front_sender = Some(sender);
+    }
+
+    /// Exports the trajectory of every new best agent into `dir`.
+    pub fn export_trajectories(&mut self, dir: PathBuf) {
+        self.trajectory_dir = Some(dir);
+    }
+
+    /// Saves every new best agent into `dir`, see `Agent::save`.
+    pub fn save_champions(&mut self, dir: PathBuf) {
+        self.champion_dir = Some(dir);
+    }
+
+    /// Keeps the best agent of this run in `hall` under `name`, replacing the entry at every
+    /// improvement.
+    pub fn enter_hall_of_fame(&mut self, hall: HallOfFame, name: String) {
+        self.hall_of_fame = Some((hall, name));
+    }
+
+    /// Evolves for the given number of generations, or forever.
+    pub fn run_generations(&mut self, generations: Option<usize>) {
+        let mut agents: Vec<CurrentAgent> = (0..10).map(|_| self.new_agent()).collect();
+        while self.keep_going(generations) {
             agents = self.selection(agents);
         }
     }
 
-    fn selection(&mut self, mut agents: Vec<CurrentAgent>) -> Vec<CurrentAgent> {
-        let mut rng = thread_rng();
+    /// Like `run_generations`, selecting for `search` with its default settings.
+    pub fn run_search(&mut self, search: Search, generations: Option<usize>) {
+        match search {
+            Search::Fitness => self.run_generations(generations),
+            Search::Novelty => self.run_novelty(&Default::default(), generations),
+            Search::MapElites => self.run_map_elites(&Default::default(), generations),
+            Search::Nsga2 => self.run_nsga2(&Default::default(), generations),
+            Search::Islands => self.run_islands(&Default::default(), generations),
+            Search::Curriculum => self.run_curriculum(&Default::default(), generations),
+        }
+    }
+
+    fn record_stats(&mut self, stats: GenerationStats) {
+        if let Some(log) = &mut self.metrics_log {
+            if let Err(err) = log.write(&stats) {
+                eprintln!("Failed to write metrics: {err}");
+                self.metrics_log = None;
+            }
+        }
+        if let Some(sender) = &self.stats_sender {
+            if sender.send(stats).is_err() {
+                self.stats_sender = None;
+            }
+        }
+    }
 
+    /// Reports `agent` to the viewer and saves it if it beats every agent so far.
+    fn consider_champion(&mut self, agent: &CurrentAgent, score: f32) {
+        if score <= self.best_score {
+            return;
+        }
+        self.best_score = score;
+        let champion = Champion {
+            agent: agent.clone(),
+            generation: self.generation,
+            score,
+        };
+        self.best = Some(champion.clone());
+        if self.sender.send(champion).is_err() {
+            // Nobody is watching anymore, so there is no point in going on.
+            self.stopped = true;
+        }
+        println!("New best score: {}", score);
+        self.improvements += 1;
+        if let Some(dir) = &self.trajectory_dir {
+            let mut trajectory = Trajectory::new();
+            pendulum::run_simulation_traced(&mut agent.clone(), &self.env, &mut trajectory);
+            let stem = dir.join(format!("best-{:04}", self.improvements));
+            if let Err(err) = trajectory.export(&stem) {
+                eprintln!("Failed to export {}: {err}", stem.display());
+            }
+        }
+        if let Some(dir) = &self.champion_dir {
+            let path = dir.join(format!("best-{:04}.agent", self.improvements));
+            if let Err(err) = agent.save(&path) {
+                eprintln!("Failed to save {}: {err}", path.display());
+            }
+        }
+        if let Some((hall, name)) = &self.hall_of_fame {
+            if let Err(err) = hall.enter(name, agent) {
+                eprintln!("Failed to enter {name} into the hall of fame: {err}");
+            }
+        }
+    }
+
+    fn selection(&mut self, agents: Vec<CurrentAgent>) -> Vec<CurrentAgent> {
         use rayon::prelude::*;
+        let start = Instant::now();
         let mut scores_and_agents: Vec<(f32, CurrentAgent)> = agents
             .into_par_iter()
-            .map(|mut agent| (pendulum::run_simulation(&mut agent), agent))
+            .map(|mut agent| (pendulum::run_simulation(&mut agent, &self.env), agent))
             .collect();
+        let evaluation_time = start.elapsed();
         scores_and_agents.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
 
+        self.generation += 1;
+        self.record_stats(GenerationStats::new(
+            self.generation,
+            &scores_and_agents,
+            evaluation_time,
+        ));
+
         let (best_score, best_agent) = scores_and_agents.last().unwrap();
-        if *best_score > self.best_score {
-            self.best_score = *best_score;
-            self.sender.send(best_agent.clone()).unwrap();
-            println!("New best score: {}", best_score);
-        }
-
-        agents = Vec::with_capacity(scores_and_agents.len());
-        agents.push(scores_and_agents.pop().unwrap().1);
-        agents.push(scores_and_agents.pop().unwrap().1);
-        agents.push(scores_and_agents.pop().unwrap().1);
-
-        let scores: Vec<f32> = scores_and_agents.iter().map(|x| x.0).collect();
-        let dist = WeightedIndex::new(&scores);
-        if let Err(WeightedError::AllWeightsZero) = dist {
-            let dist = Uniform::new(0, scores_and_agents.len());
-            for _ in 0..scores_and_agents.len() {
-                let mut agent = scores_and_agents[dist.sample(&mut rng)].1.clone();
-                agent.mutate();
-                agents.push(agent);
-            }
-        } else {
-            let dist = dist.unwrap();
-            for _ in 0..scores_and_agents.len() {
-                let mut agent = scores_and_agents[dist.sample(&mut rng)].1.clone();
-                agent.mutate();
-                agents.push(agent);
-            }
-        }
-        agents
+        self.consider_champion(best_agent, *best_score);
+
+        reproduce(scores_and_agents, &self.parent_selection)
+    }
+}
+
+/// Keeps the `elites` agents with the highest weights and fills the rest of the population
+/// with mutated copies of parents picked by `selection` among the others. `weighted` must be
+/// sorted by weight.
+fn reproduce(weighted: Vec<(f32, CurrentAgent)>, config: &SelectionConfig) -> Vec<CurrentAgent> {
+    let mut rng = thread_rng();
+    let elites = config.elites.min(weighted.len());
+    let rest = weighted.len() - elites;
+    let weights: Vec<f32> = weighted[..rest].iter().map(|x| x.0).collect();
+    let parents = config.selection.parents(&weights, rest, &mut rng);
+    let mut agents: Vec<CurrentAgent> = weighted
+        .iter()
+        .rev()
+        .take(elites)
+        .map(|x| x.1.clone())
+        .collect();
+    for i in parents {
+        let mut agent = weighted[i].1.clone();
+        agent.mutate();
+        agents.push(agent);
     }
+    agents
 }
@@ -0,0 +1,147 @@
+use super::metrics::GenerationStats;
+use super::pendulum::{self, Behaviour, PendulumAgent as CurrentAgent};
+use super::{reproduce, Ml};
+use rand::prelude::*;
+use std::time::Instant;
+
+#[derive(Clone, Debug)]
+pub struct NoveltyConfig {
+    pub population: usize,
+    /// Nearest behaviours averaged into the novelty of an agent.
+    pub neighbours: usize,
+    /// Most novel behaviours of every generation added to the archive.
+    pub archived_per_generation: usize,
+    /// Behaviours the archive holds at most. Once full, new ones replace random old ones.
+    pub archive_capacity: usize,
+}
+
+impl Default for NoveltyConfig {
+    fn default() -> Self {
+        Self {
+            population: 50,
+            neighbours: 15,
+            archived_per_generation: 2,
+            archive_capacity: 500,
+        }
+    }
+}
+
+impl Ml {
+    /// Evolves agents for behaving unlike the rest of the population and everything archived so
+    /// far, ignoring their scores for selection, which gets past the generations where no agent
+    /// scores at all. Statistics and champions still go by score.
+    pub fn run_novelty(&mut self, config: &NoveltyConfig, generations: Option<usize>) {
+        let mut archive = Vec::new();
+        let mut agents: Vec<CurrentAgent> =
+            (0..config.population).map(|_| self.new_agent()).collect();
+        while self.keep_going(generations) {
+            agents = self.novelty_selection(agents, &mut archive, config);
+        }
+    }
+
+    fn novelty_selection(
+        &mut self,
+        agents: Vec<CurrentAgent>,
+        archive: &mut Vec<Behaviour>,
+        config: &NoveltyConfig,
+    ) -> Vec<CurrentAgent> {
+        use rayon::prelude::*;
+        let start = Instant::now();
+        let (behaviours, scores_and_agents): (Vec<Behaviour>, Vec<(f32, CurrentAgent)>) = agents
+            .into_par_iter()
+            .map(|mut agent| {
+                let evaluation = pendulum::evaluate(&mut agent, &self.env);
+                (evaluation.behaviour, (evaluation.score, agent))
+            })
+            .unzip();
+        let evaluation_time = start.elapsed();
+
+        self.generation += 1;
+        self.record_stats(GenerationStats::new(
+            self.generation,
+            &scores_and_agents,
+            evaluation_time,
+        ));
+        let (best_score, best_agent) = scores_and_agents
+            .iter()
+            .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+            .unwrap();
+        self.consider_champion(best_agent, *best_score);
+
+        let mut novel: Vec<(f32, Behaviour, CurrentAgent)> = scores_and_agents
+            .into_iter()
+            .zip(&behaviours)
+            .enumerate()
+            .map(|(i, ((_, agent), behaviour))| {
+                let others = behaviours
+                    .iter()
+                    .enumerate()
+                    .filter(|&(j, _)| j != i)
+                    .map(|(_, b)| b)
+                    .chain(archive.iter());
+                (
+                    novelty(behaviour, others, config.neighbours),
+                    *behaviour,
+                    agent,
+                )
+            })
+            .collect();
+        novel.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        let mut rng = thread_rng();
+        for (_, behaviour, _) in novel.iter().rev().take(config.archived_per_generation) {
+            if archive.len() < config.archive_capacity {
+                archive.push(*behaviour);
+            } else if !archive.is_empty() {
+                let replaced = rng.gen_range(0..archive.len());
+                archive[replaced] = *behaviour;
+            }
+        }
+        let novel = novel.into_iter().map(|(n, _, agent)| (n, agent)).collect();
+        reproduce(novel, &self.parent_selection)
+    }
+}
+
+/// Mean distance from `behaviour` to its `k` nearest neighbours among `others`.
+fn novelty<'a>(
+    behaviour: &Behaviour,
+    others: impl Iterator<Item = &'a Behaviour>,
+    k: usize,
+) -> f32 {
+    let mut distances: Vec<f32> = others.map(|other| behaviour.distance(other)).collect();
+    let k = k.min(distances.len());
+    if k == 0 {
+        return 0.0;
+    }
+    if k < distances.len() {
+        distances.select_nth_unstable_by(k - 1, |a, b| a.partial_cmp(b).unwrap());
+    }
+    distances[..k].iter().sum::<f32>() / k as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn behaviour(max_height: f32, mean_cart_x: f32) -> Behaviour {
+        Behaviour {
+            max_height,
+            mean_cart_x,
+        }
+    }
+
+    #[test]
+    fn novelty_averages_the_nearest_behaviours() {
+        let others = [
+            behaviour(0.0, 0.3),
+            behaviour(0.0, -0.1),
+            behaviour(0.0, 2.0),
+            behaviour(0.0, 0.2),
+            behaviour(0.0, -5.0),
+        ];
+        let origin = behaviour(0.0, 0.0);
+        assert!((novelty(&origin, others.iter(), 3) - 0.2).abs() < 1e-6);
+        assert!((novelty(&origin, others.iter(), 10) - 7.6 / 5.0).abs() < 1e-6);
+        assert_eq!(novelty(&origin, others.iter(), 0), 0.0);
+        assert_eq!(novelty(&origin, [].iter(), 3), 0.0);
+    }
+}
@@ -0,0 +1,245 @@
+use super::metrics::GenerationStats;
+use super::pendulum::{self, Evaluation, Objectives, PendulumAgent as CurrentAgent};
+use super::{Champion, Ml};
+use rand::prelude::*;
+use std::time::Instant;
+
+/// Agents no other agent beats in every objective, ordered from the most to the least time
+/// upright.
+pub type ParetoFront = Vec<(Champion, Objectives)>;
+
+#[derive(Clone, Debug)]
+pub struct Nsga2Config {
+    pub population: usize,
+}
+
+impl Default for Nsga2Config {
+    fn default() -> Self {
+        Self { population: 50 }
+    }
+}
+
+#[derive(Clone)]
+struct Individual {
+    agent: CurrentAgent,
+    evaluation: Evaluation,
+    /// Index of the non-dominated front, 0 being the Pareto front.
+    rank: usize,
+    crowding: f32,
+}
+
+impl Ml {
+    /// Evolves agents with NSGA-II on the separate `Objectives` instead of the score, sending
+    /// the Pareto front of every generation to `report_front`. Statistics and champions still
+    /// go by score.
+    pub fn run_nsga2(&mut self, config: &Nsga2Config, generations: Option<usize>) {
+        let mut rng = thread_rng();
+        let initial = (0..config.population).map(|_| self.new_agent()).collect();
+        let mut population = self.nsga2_generation(initial);
+        population = survivors(population, config.population);
+        while self.keep_going(generations) {
+            let offspring = (0..config.population)
+                .map(|_| {
+                    let mut agent = tournament(&population, &mut rng).agent.clone();
+                    agent.mutate();
+                    agent
+                })
+                .collect();
+            population.extend(self.nsga2_generation(offspring));
+            population = survivors(population, config.population);
+            self.send_front(&population);
+        }
+    }
+
+    fn nsga2_generation(&mut self, agents: Vec<CurrentAgent>) -> Vec<Individual> {
+        use rayon::prelude::*;
+        let start = Instant::now();
+        let (evaluations, scores_and_agents): (Vec<Evaluation>, Vec<(f32, CurrentAgent)>) = agents
+            .into_par_iter()
+            .map(|mut agent| {
+                let evaluation = pendulum::evaluate(&mut agent, &self.env);
+                (evaluation, (evaluation.score, agent))
+            })
+            .unzip();
+        let evaluation_time = start.elapsed();
+
+        self.generation += 1;
+        self.record_stats(GenerationStats::new(
+            self.generation,
+            &scores_and_agents,
+            evaluation_time,
+        ));
+        let (best_score, best_agent) = scores_and_agents
+            .iter()
+            .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+            .unwrap();
+        self.consider_champion(best_agent, *best_score);
+
+        evaluations
+            .into_iter()
+            .zip(scores_and_agents)
+            .map(|(evaluation, (_, agent))| Individual {
+                agent,
+                evaluation,
+                rank: 0,
+                crowding: 0.0,
+            })
+            .collect()
+    }
+
+    fn send_front(&mut self, population: &[Individual]) {
+        let Some(sender) = &self.front_sender else {
+            return;
+        };
+        let mut front: ParetoFront = population
+            .iter()
+            .filter(|x| x.rank == 0)
+            .map(|x| {
+                let champion = Champion {
+                    agent: x.agent.clone(),
+                    generation: self.generation,
+                    score: x.evaluation.score,
+                };
+                (champion, x.evaluation.objectives)
+            })
+            .collect();
+        front.sort_by(|a, b| b.1.upright.partial_cmp(&a.1.upright).unwrap());
+        if sender.send(front).is_err() {
+            self.front_sender = None;
+        }
+    }
+}
+
+/// Ranks `population` into non-dominated fronts and keeps the best `size`, preferring the less
+/// crowded individuals of the front that doesn't fit whole.
+fn survivors(mut population: Vec<Individual>, size: usize) -> Vec<Individual> {
+    let objectives: Vec<Objectives> = population.iter().map(|x| x.evaluation.objectives).collect();
+    let fronts = non_dominated_fronts(&objectives);
+    for (rank, front) in fronts.iter().enumerate() {
+        for (&i, crowding) in front.iter().zip(crowding_distances(front, &objectives)) {
+            population[i].rank = rank;
+            population[i].crowding = crowding;
+        }
+    }
+    population.sort_by(|a, b| {
+        a.rank
+            .cmp(&b.rank)
+            .then(b.crowding.partial_cmp(&a.crowding).unwrap())
+    });
+    population.truncate(size);
+    population
+}
+
+/// Indices of `objectives` grouped into fronts, each dominated only by earlier ones.
+fn non_dominated_fronts(objectives: &[Objectives]) -> Vec<Vec<usize>> {
+    let mut dominated: Vec<Vec<usize>> = vec![Vec::new(); objectives.len()];
+    let mut domination_count = vec![0; objectives.len()];
+    for (i, a) in objectives.iter().enumerate() {
+        for (j, b) in objectives.iter().enumerate() {
+            if a.dominates(b) {
+                dominated[i].push(j);
+            } else if b.dominates(a) {
+                domination_count[i] += 1;
+            }
+        }
+    }
+    let mut fronts = Vec::new();
+    let mut front: Vec<usize> = (0..objectives.len())
+        .filter(|&i| domination_count[i] == 0)
+        .collect();
+    while !front.is_empty() {
+        let mut next = Vec::new();
+        for &i in &front {
+            for &j in &dominated[i] {
+                domination_count[j] -= 1;
+                if domination_count[j] == 0 {
+                    next.push(j);
+                }
+            }
+        }
+        fronts.push(front);
+        front = next;
+    }
+    fronts
+}
+
+/// How far each member of `front` is from its neighbours along every objective, infinite at
+/// the extremes so that those are always kept.
+fn crowding_distances(front: &[usize], objectives: &[Objectives]) -> Vec<f32> {
+    let mut distances = vec![0.0; front.len()];
+    for m in 0..Objectives::COUNT {
+        let value = |k: usize| objectives[front[k]].to_array()[m];
+        let mut order: Vec<usize> = (0..front.len()).collect();
+        order.sort_by(|&a, &b| value(a).partial_cmp(&value(b)).unwrap());
+        let (first, last) = (order[0], order[order.len() - 1]);
+        distances[first] = f32::INFINITY;
+        distances[last] = f32::INFINITY;
+        let range = value(last) - value(first);
+        if range <= 0.0 {
+            continue;
+        }
+        for w in order.windows(3) {
+            distances[w[1]] += (value(w[2]) - value(w[0])) / range;
+        }
+    }
+    distances
+}
+
+/// Binary tournament on rank, then crowding.
+fn tournament<'a>(population: &'a [Individual], rng: &mut impl Rng) -> &'a Individual {
+    let a = population.choose(rng).unwrap();
+    let b = population.choose(rng).unwrap();
+    if (a.rank, -a.crowding) <= (b.rank, -b.crowding) {
+        a
+    } else {
+        b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn objectives(values: &[[f32; 3]]) -> Vec<Objectives> {
+        values
+            .iter()
+            .map(|&[upright, smoothness, centring]| Objectives {
+                upright,
+                smoothness,
+                centring,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fronts_are_dominated_only_by_earlier_ones() {
+        let objectives = objectives(&[
+            [0.5, -0.5, -0.5],
+            [1.0, -0.2, -0.1],
+            [0.2, -0.1, -0.3],
+            [0.1, -0.9, -0.9],
+            [0.5, -0.5, -0.5],
+        ]);
+        let mut fronts = non_dominated_fronts(&objectives);
+        for front in &mut fronts {
+            front.sort();
+        }
+        assert_eq!(fronts, vec![vec![1, 2], vec![0, 4], vec![3]]);
+    }
+
+    #[test]
+    fn crowding_is_infinite_at_the_extremes() {
+        let objectives = objectives(&[
+            [0.0, -1.0, -0.5],
+            [0.25, -0.75, -0.5],
+            [0.5, -0.5, -0.5],
+            [1.0, 0.0, -0.5],
+        ]);
+        let distances = crowding_distances(&[0, 1, 2, 3], &objectives);
+        assert_eq!(distances[0], f32::INFINITY);
+        assert_eq!(distances[3], f32::INFINITY);
+        // Normalised gaps of 0.5 in the first two objectives, nothing from the constant third.
+        assert!((distances[1] - 1.0).abs() < 1e-6);
+        assert!((distances[2] - 1.5).abs() < 1e-6);
+    }
+}
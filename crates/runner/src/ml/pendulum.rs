@@ -1,5 +1,13 @@
+use super::activation::Activation;
 use super::Agent;
-use crate::pendulum::Pendulum;
+use crate::actuator::{bang_bang, Actuator, ActuatorConfig};
+use crate::estimator::{Estimator, EstimatorConfig, KalmanConfig, Model, StateEstimator};
+use crate::pendulum::{bob_offset, Pendulum, PendulumState};
+use crate::sensor::{SensorConfig, Sensors};
+use crate::trajectory::Trajectory;
+use glam::Vec2;
+use rand::prelude::*;
+use rand::rngs::StdRng;
 use std::time::Duration;
 
 #[derive(Clone)]
@@ -10,6 +18,12 @@ pub struct Inputs {
     pub angvel: f32,
 }
 
+impl Inputs {
+    pub fn to_array(&self) -> [f32; 4] {
+        [self.cart_x, self.bob_x, self.bob_y, self.angvel]
+    }
+}
+
 impl super::Inputs for Inputs {
     const COUNT: usize = 4;
 
@@ -22,6 +36,10 @@ impl super::Inputs for Inputs {
             _ => panic!(),
         }
     }
+
+    fn name(index: usize) -> &'static str {
+        ["cart_x", "bob_x", "bob_y", "angvel"][index]
+    }
 }
 
 pub type PendulumAgent = Agent<Inputs, Outputs>;
@@ -34,6 +52,14 @@ pub struct Outputs {
 impl super::Outputs for Outputs {
     const COUNT: usize = 1;
 
+    fn name(index: usize) -> &'static str {
+        ["speed"][index]
+    }
+
+    fn squash(_index: usize) -> Activation {
+        Activation::Tanh
+    }
+
     fn from_iter<I: Iterator<Item = f32>>(mut it: I) -> Self {
         Self {
             speed: it.next().unwrap(),
@@ -41,34 +67,388 @@ impl super::Outputs for Outputs {
     }
 }
 
-pub fn set_pendulum_inputs(pendulum: &mut Pendulum, agent: &mut PendulumAgent) {
-    let outputs = agent.choose(Inputs {
-        cart_x: pendulum.cart_x(),
-        bob_x: pendulum.bob_pos().x,
-        bob_y: pendulum.bob_pos().y,
-        angvel: pendulum.angvel(),
-    });
-    let speed = outputs.speed;
-    if speed > 0.1 {
-        pendulum.move_right();
-    } else if speed < -0.1 {
-        pendulum.move_left();
+/// Models of the physical equipment around the pendulum, shared by training and the viewer.
+#[derive(Clone, Default)]
+pub struct EnvConfig {
+    pub sensors: SensorConfig,
+    pub actuator: ActuatorConfig,
+    pub estimator: EstimatorConfig,
+}
+
+impl EnvConfig {
+    pub fn realistic() -> Self {
+        let sensors = SensorConfig::realistic();
+        Self {
+            estimator: EstimatorConfig::Kalman(KalmanConfig::for_sensors(
+                Model::Nonlinear,
+                &sensors,
+            )),
+            sensors,
+            actuator: ActuatorConfig::realistic(),
+        }
+    }
+
+    /// Selects a preset with the `PENDULUM_ENV` environment variable (`ideal` or `realistic`),
+    /// and optionally overrides its estimator with `PENDULUM_ESTIMATOR` (`none`, `kalman` or
+    /// `ekf`). Unknown values are reported and ignored.
+    pub fn from_env() -> Self {
+        let mut config = match std::env::var("PENDULUM_ENV").as_deref() {
+            Ok("realistic") => Self::realistic(),
+            Ok("ideal") | Err(_) => Self::default(),
+            Ok(other) => {
+                eprintln!("Unknown PENDULUM_ENV: {other}, using `ideal`");
+                Self::default()
+            }
+        };
+        match std::env::var("PENDULUM_ESTIMATOR").as_deref() {
+            Ok("none") => config.estimator = EstimatorConfig::Passthrough,
+            Ok("kalman") => {
+                config.estimator = EstimatorConfig::Kalman(KalmanConfig::for_sensors(
+                    Model::Upright,
+                    &config.sensors,
+                ))
+            }
+            Ok("ekf") => {
+                config.estimator = EstimatorConfig::Kalman(KalmanConfig::for_sensors(
+                    Model::Nonlinear,
+                    &config.sensors,
+                ))
+            }
+            Err(_) => {}
+            Ok(other) => eprintln!("Unknown PENDULUM_ESTIMATOR: {other}, keeping the preset's"),
+        }
+        config
+    }
+}
+
+#[derive(Clone)]
+pub struct Hardware {
+    sensors: Sensors,
+    actuator: Actuator,
+    estimator: Estimator,
+}
+
+impl Hardware {
+    pub fn new(config: &EnvConfig) -> Self {
+        Self::with_sensors(config, Sensors::new(config.sensors.clone()))
+    }
+
+    /// Hardware whose sensor errors are the same every time for the same `seed`.
+    pub fn seeded(config: &EnvConfig, seed: u64) -> Self {
+        Self::with_sensors(config, Sensors::seeded(config.sensors.clone(), seed))
+    }
+
+    fn with_sensors(config: &EnvConfig, sensors: Sensors) -> Self {
+        Self {
+            sensors,
+            actuator: Actuator::new(config.actuator.clone()),
+            estimator: Estimator::new(&config.estimator),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.sensors.reset();
+        self.actuator.reset();
+        self.estimator.reset();
+    }
+}
+
+const EPISODE_STEPS: usize = 30 * 100;
+
+/// Lets the agent choose a command for the cart and returns it.
+pub fn set_pendulum_inputs(
+    pendulum: &mut Pendulum,
+    hardware: &mut Hardware,
+    agent: &mut PendulumAgent,
+    delta: Duration,
+) -> f32 {
+    let inputs = observe(pendulum, hardware, delta);
+    let outputs = agent.choose(inputs);
+    act(pendulum, hardware, outputs.speed, delta)
+}
+
+/// What a controller gets to see of the pendulum through the sensors and estimator.
+fn observe(pendulum: &Pendulum, hardware: &mut Hardware, delta: Duration) -> Inputs {
+    let measurement = hardware.sensors.observe(pendulum, delta);
+    let estimate = hardware
+        .estimator
+        .update(measurement, hardware.actuator.acceleration(), delta);
+    let bob_pos = estimate.bob_pos();
+    Inputs {
+        cart_x: estimate.cart_x,
+        bob_x: bob_pos.x,
+        bob_y: bob_pos.y,
+        angvel: estimate.angvel,
+    }
+}
+
+/// Drives the cart for the requested speed and returns the command sent to the actuator.
+fn act(pendulum: &mut Pendulum, hardware: &mut Hardware, speed: f32, delta: Duration) -> f32 {
+    hardware.actuator.drive(pendulum, speed, delta)
+}
+
+/// Inputs an agent would see along `trajectory` with perfect sensors.
+pub fn inputs_along(trajectory: &Trajectory) -> Vec<Inputs> {
+    trajectory
+        .states()
+        .map(|state| {
+            let bob_pos = Vec2::X * state.cart_x + bob_offset(state.bob_angle);
+            Inputs {
+                cart_x: state.cart_x,
+                bob_x: bob_pos.x,
+                bob_y: bob_pos.y,
+                angvel: state.bob_angvel,
+            }
+        })
+        .collect()
+}
+
+/// First step of `trajectory` at which the two agents would command the cart differently.
+pub fn first_divergence(
+    a: &mut PendulumAgent,
+    b: &mut PendulumAgent,
+    trajectory: &Trajectory,
+) -> Option<usize> {
+    a.reset_state();
+    b.reset_state();
+    inputs_along(trajectory).into_iter().position(|inputs| {
+        bang_bang(a.choose(inputs.clone()).speed) != bang_bang(b.choose(inputs).speed)
+    })
+}
+
+/// Normalised bob height above which a step counts as upright and earns a reward.
+const UPRIGHT_HEIGHT: f32 = 0.9;
+
+/// Score earned by a single step of `run_simulation`.
+pub fn step_reward(pendulum: &Pendulum) -> f32 {
+    let y = pendulum.bob_pos_normalized().y;
+    if y > UPRIGHT_HEIGHT {
+        y / (pendulum.angvel().abs() * 4.0 + 1.0) / (1.0 + pendulum.cart_x().abs())
     } else {
-        pendulum.stop();
+        0.0
+    }
+}
+
+/// What an agent did in an episode regardless of how it scored, for the searches that reward
+/// new behaviour rather than fitness.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Behaviour {
+    /// Highest the bob got, from -1 hanging straight down to 1 straight up.
+    pub max_height: f32,
+    /// Where the cart spent the episode on average, from -0.5 to 0.5.
+    pub mean_cart_x: f32,
+}
+
+impl Behaviour {
+    /// Both descriptors span a similar range, so plain Euclidean distance weighs them evenly.
+    pub fn distance(&self, other: &Self) -> f32 {
+        let height = (self.max_height - other.max_height) / 2.0;
+        let cart_x = self.mean_cart_x - other.mean_cart_x;
+        (height * height + cart_x * cart_x).sqrt()
     }
 }
 
-pub fn run_simulation(agent: &mut PendulumAgent) -> f32 {
-    let mut pendulum = Pendulum::new();
+/// Separate goals that the score folds into a single number, each to be maximised.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Objectives {
+    /// Fraction of the episode with the bob near the top.
+    pub upright: f32,
+    /// Negated mean magnitude of the commands, from -1 for always at full speed to 0 for never
+    /// moving.
+    pub smoothness: f32,
+    /// Negated mean distance of the cart from the centre of the track.
+    pub centring: f32,
+}
+
+impl Objectives {
+    pub const COUNT: usize = 3;
+
+    pub fn to_array(self) -> [f32; Self::COUNT] {
+        [self.upright, self.smoothness, self.centring]
+    }
+
+    /// Whether `self` is at least as good in every objective and better in one.
+    pub fn dominates(&self, other: &Self) -> bool {
+        let (a, b) = (self.to_array(), other.to_array());
+        a.iter().zip(&b).all(|(a, b)| a >= b) && a.iter().zip(&b).any(|(a, b)| a > b)
+    }
+}
+
+/// Everything an episode says about an agent.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Evaluation {
+    pub score: f32,
+    pub behaviour: Behaviour,
+    pub objectives: Objectives,
+}
+
+/// Conditions an episode starts from and runs under. The default is the episode of
+/// `run_simulation`: hanging still, full length and undisturbed.
+#[derive(Clone, Copy, Debug)]
+pub struct Scenario {
+    pub start: PendulumState,
+    pub steps: usize,
+    /// Sideways force the bob is pushed with for a step, about once a second. 0 for none.
+    pub disturbance: f32,
+    /// Seed of the pushes, so that agents given the same scenario get the same ones.
+    pub seed: u64,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self {
+            start: PendulumState::hanging(),
+            steps: EPISODE_STEPS,
+            disturbance: 0.0,
+            seed: 0,
+        }
+    }
+}
+
+impl Scenario {
+    /// The default scenario with a seed of its own, so that every standard episode draws new
+    /// sensor errors rather than training agents on one particular draw.
+    fn fresh() -> Self {
+        Self {
+            seed: thread_rng().gen(),
+            ..Self::default()
+        }
+    }
+}
+
+/// Scores `agent` on the standard episode, with new sensor errors every time.
+pub fn run_simulation(agent: &mut PendulumAgent, env: &EnvConfig) -> f32 {
+    simulate(agent, env, &Scenario::fresh(), None).score
+}
+
+/// Like `run_simulation`, additionally describing what the agent did and how it did on every
+/// objective.
+pub fn evaluate(agent: &mut PendulumAgent, env: &EnvConfig) -> Evaluation {
+    simulate(agent, env, &Scenario::fresh(), None)
+}
+
+/// Like `evaluate`, in the given scenario instead of the standard one. The same scenario always
+/// gives the same sensor errors.
+pub fn evaluate_scenario(
+    agent: &mut PendulumAgent,
+    env: &EnvConfig,
+    scenario: &Scenario,
+) -> Evaluation {
+    simulate(agent, env, scenario, None)
+}
+
+/// Like `run_simulation`, additionally recording every step.
+pub fn run_simulation_traced(
+    agent: &mut PendulumAgent,
+    env: &EnvConfig,
+    trajectory: &mut Trajectory,
+) -> f32 {
+    simulate(agent, env, &Scenario::fresh(), Some(trajectory)).score
+}
+
+/// Mixed into `Scenario::seed` to seed the sensors.
+const SENSOR_SEED_SALT: u64 = 0x5eed_5e45_0b5e_4e00;
+
+fn simulate(
+    agent: &mut PendulumAgent,
+    env: &EnvConfig,
+    scenario: &Scenario,
+    mut trajectory: Option<&mut Trajectory>,
+) -> Evaluation {
+    let mut pendulum = Pendulum::from_state(scenario.start);
+    // Different from the pushes, which would otherwise line up with the sensor errors.
+    let mut hardware = Hardware::seeded(env, scenario.seed ^ SENSOR_SEED_SALT);
+    let mut pushes = StdRng::seed_from_u64(scenario.seed);
+    agent.reset_state();
     let delta = Duration::from_secs_f64(1.0 / 30.0);
     let mut score = 0.0;
-    for _ in 0..30 * 100 {
-        set_pendulum_inputs(&mut pendulum, agent);
+    let mut max_height = f32::MIN;
+    let mut cart_x_sum = 0.0;
+    let mut upright_steps = 0;
+    let mut effort = 0.0;
+    let mut cart_distance = 0.0;
+    for step in 0..scenario.steps {
+        let action = set_pendulum_inputs(&mut pendulum, &mut hardware, agent, delta);
+        if scenario.disturbance > 0.0 && pushes.gen::<f32>() < delta.as_secs_f32() {
+            let direction = if pushes.gen() { 1.0 } else { -1.0 };
+            pendulum.push_bob(Vec2::X * direction * scenario.disturbance);
+        }
         pendulum.update(delta);
-        let y = pendulum.bob_pos_normalized().y;
-        if y > 0.9 {
-            score += y / (pendulum.angvel().abs() * 4.0 + 1.0) / (1.0 + pendulum.cart_x().abs());
+        let reward = step_reward(&pendulum);
+        score += reward;
+        let height = pendulum.bob_pos_normalized().y;
+        max_height = max_height.max(height);
+        cart_x_sum += pendulum.cart_x();
+        if height > UPRIGHT_HEIGHT {
+            upright_steps += 1;
+        }
+        effort += action.abs();
+        cart_distance += pendulum.cart_x().abs();
+        if let Some(trajectory) = &mut trajectory {
+            let time = (step + 1) as f32 * delta.as_secs_f32();
+            trajectory.push(time, &pendulum, action, reward);
         }
     }
-    score
+    let steps = scenario.steps as f32;
+    Evaluation {
+        score,
+        behaviour: Behaviour {
+            max_height,
+            mean_cart_x: cart_x_sum / steps,
+        },
+        objectives: Objectives {
+            upright: upright_steps as f32 / steps,
+            smoothness: -effort / steps,
+            centring: -cart_distance / steps,
+        },
+    }
+}
+
+/// Runs an episode like `run_simulation`, with `policy` choosing the speed from each
+/// observation, and returns the reward of every step.
+pub fn run_policy(env: &EnvConfig, mut policy: impl FnMut(&Inputs) -> f32) -> Vec<f32> {
+    let mut episode = Episode::new(env);
+    let mut rewards = Vec::with_capacity(EPISODE_STEPS);
+    while !episode.is_over() {
+        let inputs = episode.observe();
+        rewards.push(episode.act(policy(&inputs)));
+    }
+    rewards
+}
+
+/// An episode like those of `run_simulation`, driven one step at a time by a controller that
+/// isn't an agent, for learners that need every reward as it comes.
+pub struct Episode {
+    pendulum: Pendulum,
+    hardware: Hardware,
+    steps: usize,
+}
+
+impl Episode {
+    const DELTA: Duration = Duration::from_nanos(1_000_000_000 / 30);
+
+    pub fn new(env: &EnvConfig) -> Self {
+        Self {
+            pendulum: Pendulum::new(),
+            hardware: Hardware::new(env),
+            steps: 0,
+        }
+    }
+
+    /// Reads the sensors for the next step. Call it once before every `act`.
+    pub fn observe(&mut self) -> Inputs {
+        observe(&self.pendulum, &mut self.hardware, Self::DELTA)
+    }
+
+    /// Drives the cart for the requested speed, advances the simulation and returns the reward.
+    pub fn act(&mut self, speed: f32) -> f32 {
+        act(&mut self.pendulum, &mut self.hardware, speed, Self::DELTA);
+        self.pendulum.update(Self::DELTA);
+        self.steps += 1;
+        step_reward(&self.pendulum)
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.steps >= EPISODE_STEPS
+    }
 }
@@ -0,0 +1,153 @@
+use super::{Agent, Inputs, Outputs};
+use daggy::petgraph::stable_graph::{edge_index, node_index};
+use daggy::Walker;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PruneReport {
+    pub nodes_removed: usize,
+    pub edges_removed: usize,
+}
+
+impl<I: Inputs, O: Outputs> Agent<I, O> {
+    /// Removes edges weaker than `min_weight`, then every hidden node without a path to an
+    /// output, which also drops subgraphs disconnected from the outputs. Only the weak edges
+    /// can change what the agent chooses, so `min_weight` of zero keeps its behaviour.
+    pub fn prune(&mut self, min_weight: f32) -> PruneReport {
+        let edges_before = self.dag.edge_count();
+        let weak_edges: Vec<usize> = self
+            .dag
+            .raw_edges()
+            .iter()
+            .enumerate()
+            .filter(|(_, edge)| edge.weight.weight.abs() < min_weight)
+            .map(|(i, _)| i)
+            .collect();
+        // Removing an edge moves the last one into its index, so go from the back.
+        for &i in weak_edges.iter().rev() {
+            self.dag.remove_edge(edge_index(i));
+        }
+        self.recurrent.retain(|r| r.edge.weight.abs() >= min_weight);
+
+        let useful = self.reaches_output();
+        let first_hidden = I::COUNT + O::COUNT;
+        let mut removed_nodes = 0;
+        for i in (first_hidden..self.dag.node_count()).rev() {
+            if !useful[i] {
+                self.remove_hidden_node(i);
+                removed_nodes += 1;
+            }
+        }
+        PruneReport {
+            nodes_removed: removed_nodes,
+            edges_removed: edges_before - self.dag.edge_count(),
+        }
+    }
+
+    /// Whether each node has a path to some output.
+    fn reaches_output(&self) -> Vec<bool> {
+        let mut useful = vec![false; self.dag.node_count()];
+        let mut stack: Vec<usize> = (I::COUNT..I::COUNT + O::COUNT).collect();
+        while let Some(i) = stack.pop() {
+            if useful[i] {
+                continue;
+            }
+            useful[i] = true;
+            let mut parents = self.dag.parents(node_index(i));
+            while let Some((_, parent)) = parents.walk_next(&self.dag) {
+                stack.push(parent.index());
+            }
+            stack.extend(
+                self.recurrent
+                    .iter()
+                    .filter(|r| r.target == i)
+                    .map(|r| r.source),
+            );
+        }
+        useful
+    }
+
+    /// Largest difference between the outputs of two agents over the same sequence of inputs,
+    /// starting from a reset state.
+    pub fn max_output_difference(&mut self, other: &mut Self, inputs: &[I]) -> f32
+    where
+        I: Clone,
+    {
+        self.reset_state();
+        other.reset_state();
+        let mut difference: f32 = 0.0;
+        for input in inputs {
+            self.choose(input.clone());
+            other.choose(input.clone());
+            for (a, b) in self.output_values().zip(other.output_values()) {
+                difference = difference.max((a - b).abs());
+            }
+        }
+        difference
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::pendulum::{Inputs, PendulumAgent};
+    use super::super::{Edge, Node};
+    use super::*;
+
+    fn inputs() -> Vec<Inputs> {
+        (0..50)
+            .map(|i| {
+                let t = i as f32 * 0.1;
+                Inputs {
+                    cart_x: 0.4 * t.sin(),
+                    bob_x: t.cos(),
+                    bob_y: -t.sin(),
+                    angvel: 3.0 * (2.0 * t).cos(),
+                }
+            })
+            .collect()
+    }
+
+    /// An evolved-looking agent with a hidden node that only an input feeds.
+    fn agent_with_dead_node() -> PendulumAgent {
+        let mut agent = PendulumAgent::new();
+        for _ in 0..40 {
+            agent.mutate();
+        }
+        let dead = agent.dag.add_node(Node::random(PendulumAgent::hidden_id()));
+        agent
+            .dag
+            .add_edge(node_index(0), dead, Edge::new(0.5))
+            .unwrap();
+        agent
+    }
+
+    #[test]
+    fn pruning_without_a_threshold_keeps_the_outputs() {
+        let inputs = inputs();
+        for _ in 0..100 {
+            let mut agent = agent_with_dead_node();
+            let mut pruned = agent.clone();
+            let report = pruned.prune(0.0);
+            assert!(report.nodes_removed >= 1);
+            assert!(pruned.node_count() < agent.node_count());
+            assert_eq!(agent.max_output_difference(&mut pruned, &inputs), 0.0);
+        }
+    }
+
+    #[test]
+    fn pruning_removes_weak_edges() {
+        let mut agent = agent_with_dead_node();
+        let weak = agent
+            .dag
+            .raw_edges()
+            .iter()
+            .filter(|e| e.weight.weight.abs() < 0.3)
+            .count();
+        let report = agent.prune(0.3);
+        assert!(report.edges_removed >= weak);
+        assert!(agent
+            .dag
+            .raw_edges()
+            .iter()
+            .all(|e| e.weight.weight.abs() >= 0.3));
+    }
+}
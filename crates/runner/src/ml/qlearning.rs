@@ -0,0 +1,278 @@
+//! Value-based baselines: Q-learning and SARSA over the left, stop and right commands of
+//! `actuator::bang_bang`, with the observations discretised into a table or tile-coded for linear
+//! function approximation.
+
+use super::metrics::GenerationStats;
+use super::pendulum::{self, Episode};
+use super::Ml;
+use rand::prelude::*;
+use std::str::FromStr;
+use std::time::Instant;
+
+/// Speeds that `actuator::bang_bang` turns into each of the commands.
+const SPEEDS: [f32; 3] = [-1.0, 0.0, 1.0];
+/// Range of each observation covered by the tiles. Values outside fall into the edge tiles.
+const RANGES: [(f32, f32); 4] = [(-0.5, 0.5), (-0.9, 0.9), (-0.4, 0.4), (-10.0, 10.0)];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    /// Off-policy, bootstrapping from the best action of the next state.
+    QLearning,
+    /// On-policy, bootstrapping from the action actually taken next.
+    Sarsa,
+}
+
+impl FromStr for Method {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "q-learning" => Ok(Self::QLearning),
+            "sarsa" => Ok(Self::Sarsa),
+            _ => Err(format!("unknown method `{s}`")),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct QConfig {
+    pub method: Method,
+    /// Tiles across the range of every observation.
+    pub bins: usize,
+    /// Overlapping, offset tilings. One is a plain lookup table.
+    pub tilings: usize,
+    /// Step size of a full update, shared between the tilings.
+    pub learning_rate: f32,
+    pub discount: f32,
+    /// Value every action starts out with. Rewards only come near the top, so optimistic values
+    /// are what drives the early episodes to try every command everywhere.
+    pub initial_value: f32,
+    /// Chance of a random command at the start, decaying after every episode down to
+    /// `min_exploration`.
+    pub exploration: f32,
+    pub exploration_decay: f32,
+    pub min_exploration: f32,
+    /// Episodes reported together as one generation.
+    pub episodes: usize,
+    /// Generations to run, or `None` to keep going.
+    pub iterations: Option<usize>,
+}
+
+impl Default for QConfig {
+    fn default() -> Self {
+        Self {
+            method: Method::QLearning,
+            bins: 8,
+            tilings: 8,
+            learning_rate: 0.1,
+            discount: 0.99,
+            initial_value: 20.0,
+            exploration: 0.2,
+            exploration_decay: 0.99,
+            min_exploration: 0.01,
+            episodes: 10,
+            iterations: None,
+        }
+    }
+}
+
+/// Maps an observation to one active tile in each tiling. Tiling `t` is shifted by a different
+/// fraction of a tile along every dimension, which spreads the tilings more evenly than shifting
+/// them all along the diagonal.
+struct TileCoder {
+    bins: usize,
+    tilings: usize,
+}
+
+impl TileCoder {
+    /// Tiles per tiling, with one more along every dimension to make room for the offsets.
+    fn tiles(&self) -> usize {
+        (self.bins + 1).pow(RANGES.len() as u32)
+    }
+
+    fn features(&self, observation: &[f32; 4]) -> Vec<usize> {
+        (0..self.tilings)
+            .map(|t| {
+                let tiles = RANGES.iter().zip(observation).enumerate().fold(
+                    0,
+                    |index, (d, (&(low, high), &x))| {
+                        let offset =
+                            ((t * (2 * d + 1)) % self.tilings) as f32 / self.tilings as f32;
+                        let position = ((x - low) / (high - low)).clamp(0.0, 1.0);
+                        let tile = (position * self.bins as f32 + offset) as usize;
+                        index * (self.bins + 1) + tile.min(self.bins)
+                    },
+                );
+                t * self.tiles() + tiles
+            })
+            .collect()
+    }
+}
+
+/// Action values linear in the tile features, one weight vector per command.
+struct QFunction {
+    coder: TileCoder,
+    weights: Vec<[f32; SPEEDS.len()]>,
+}
+
+impl QFunction {
+    fn new(config: &QConfig) -> Self {
+        let coder = TileCoder {
+            bins: config.bins,
+            tilings: config.tilings.max(1),
+        };
+        let initial = config.initial_value / coder.tilings as f32;
+        let weights = vec![[initial; SPEEDS.len()]; coder.tiles() * coder.tilings];
+        Self { coder, weights }
+    }
+
+    fn values(&self, features: &[usize]) -> [f32; SPEEDS.len()] {
+        let mut values = [0.0; SPEEDS.len()];
+        for &f in features {
+            for (v, w) in values.iter_mut().zip(&self.weights[f]) {
+                *v += w;
+            }
+        }
+        values
+    }
+
+    fn best_value(&self, features: &[usize]) -> f32 {
+        self.values(features).into_iter().fold(f32::MIN, f32::max)
+    }
+
+    /// The action with the highest value, breaking ties at random.
+    fn greedy(&self, features: &[usize], rng: &mut impl Rng) -> usize {
+        let values = self.values(features);
+        let best = values.into_iter().fold(f32::MIN, f32::max);
+        let ties: Vec<usize> = (0..values.len()).filter(|&a| values[a] == best).collect();
+        *ties.choose(rng).unwrap()
+    }
+
+    fn epsilon_greedy(&self, features: &[usize], exploration: f32, rng: &mut impl Rng) -> usize {
+        if rng.gen::<f32>() < exploration {
+            rng.gen_range(0..SPEEDS.len())
+        } else {
+            self.greedy(features, rng)
+        }
+    }
+
+    /// Moves the value of `action` towards `target` by `step` of the difference.
+    fn update(&mut self, features: &[usize], action: usize, target: f32, step: f32) {
+        let error = target - self.values(features)[action];
+        let share = step * error / features.len() as f32;
+        for &f in features {
+            self.weights[f][action] += share;
+        }
+    }
+}
+
+impl Ml {
+    /// Learns action values online, one episode after another, reporting every
+    /// `config.episodes` episodes like a generation of `run_generations`. The scores are those
+    /// of the exploring policy, and nothing is sent to the viewer or saved.
+    pub fn run_q_learning(&mut self, config: &QConfig) {
+        let mut q = QFunction::new(config);
+        let mut exploration = config.exploration;
+        let mut rng = thread_rng();
+        while self.keep_going(config.iterations) {
+            let start = Instant::now();
+            let scores: Vec<f32> = (0..config.episodes)
+                .map(|_| {
+                    let score = q_episode(&mut q, &self.env, config, exploration, &mut rng);
+                    exploration =
+                        (exploration * config.exploration_decay).max(config.min_exploration);
+                    score
+                })
+                .collect();
+            let evaluation_time = start.elapsed();
+
+            self.generation += 1;
+            let best = scores.iter().copied().fold(f32::MIN, f32::max);
+            self.record_stats(GenerationStats::from_scores(
+                self.generation,
+                &scores,
+                evaluation_time,
+            ));
+            if best > self.best_score {
+                self.best_score = best;
+                println!("New best score: {best}");
+            }
+        }
+    }
+}
+
+/// Runs and learns from one episode, returning its score. The last step is treated as
+/// terminal.
+fn q_episode(
+    q: &mut QFunction,
+    env: &pendulum::EnvConfig,
+    config: &QConfig,
+    exploration: f32,
+    rng: &mut impl Rng,
+) -> f32 {
+    let mut episode = Episode::new(env);
+    let mut features = q.coder.features(&episode.observe().to_array());
+    let mut action = q.epsilon_greedy(&features, exploration, rng);
+    let mut score = 0.0;
+    loop {
+        let reward = episode.act(SPEEDS[action]);
+        score += reward;
+        if episode.is_over() {
+            q.update(&features, action, reward, config.learning_rate);
+            return score;
+        }
+        let next_features = q.coder.features(&episode.observe().to_array());
+        let next_action = q.epsilon_greedy(&next_features, exploration, rng);
+        let next_value = match config.method {
+            Method::QLearning => q.best_value(&next_features),
+            Method::Sarsa => q.values(&next_features)[next_action],
+        };
+        q.update(
+            &features,
+            action,
+            reward + config.discount * next_value,
+            config.learning_rate,
+        );
+        features = next_features;
+        action = next_action;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tile_features_stay_in_range() {
+        let coder = TileCoder {
+            bins: 6,
+            tilings: 4,
+        };
+        let lows = RANGES.map(|r| r.0);
+        let highs = RANGES.map(|r| r.1);
+        for observation in [lows, highs, lows.map(|x| x * 3.0), highs.map(|x| x * 3.0)] {
+            let features = coder.features(&observation);
+            assert_eq!(features.len(), 4);
+            for (t, &feature) in features.iter().enumerate() {
+                assert!(feature < coder.tiles() * coder.tilings);
+                assert_eq!(feature / coder.tiles(), t);
+            }
+        }
+    }
+
+    #[test]
+    fn single_tiling_is_a_table() {
+        let coder = TileCoder {
+            bins: 4,
+            tilings: 1,
+        };
+        // Bins 1, 3, 0 and 2 of the dimensions, each with one spare bin for the offsets.
+        let observation = [-0.2, 0.5, -0.35, 2.0];
+        let index = [1, 3, 0, 2]
+            .into_iter()
+            .fold(0, |index, bin| index * (coder.bins + 1) + bin);
+        assert_eq!(coder.features(&observation), [index]);
+        // Values beyond the ranges land in the edge bins.
+        assert_eq!(coder.features(&[-1.0, -5.0, -1.0, -20.0]), [0]);
+    }
+}
@@ -0,0 +1,470 @@
+//! Gradient-based reinforcement learning: a Gaussian policy and a value baseline, both small
+//! multilayer perceptrons, trained with REINFORCE or PPO on the same episodes and rewards as the
+//! evolved agents, for comparing the two on the CPU.
+
+use super::autodiff::{Tape, Tensor, Var};
+use super::metrics::GenerationStats;
+use super::pendulum::{self, Inputs};
+use super::Ml;
+use crate::sensor::gaussian;
+use rand::prelude::*;
+use std::str::FromStr;
+use std::time::Instant;
+
+const OBSERVATIONS: usize = <Inputs as super::Inputs>::COUNT;
+/// Largest norm of the gradient of every parameter together, to keep one bad batch from
+/// wrecking the policy.
+const MAX_GRADIENT_NORM: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    /// Policy gradient on Monte Carlo returns, with the value network as a baseline.
+    Reinforce,
+    /// Proximal policy optimisation with the clipped objective and generalised advantages.
+    Ppo,
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reinforce" => Ok(Self::Reinforce),
+            "ppo" => Ok(Self::Ppo),
+            _ => Err(format!("unknown algorithm `{s}`")),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RlConfig {
+    pub algorithm: Algorithm,
+    /// Width of the two hidden layers of both networks.
+    pub hidden: usize,
+    /// Episodes collected before every update.
+    pub episodes: usize,
+    pub learning_rate: f32,
+    pub discount: f32,
+    /// Generalised advantage estimation λ. REINFORCE always uses 1, the Monte Carlo return.
+    pub gae_lambda: f32,
+    /// How far PPO lets the probability ratio move from 1.
+    pub clip: f32,
+    /// Passes over every batch of episodes. REINFORCE makes a single one.
+    pub epochs: usize,
+    pub minibatch: usize,
+    /// Updates to run, or `None` to keep going.
+    pub iterations: Option<usize>,
+}
+
+impl Default for RlConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: Algorithm::Ppo,
+            hidden: 32,
+            episodes: 8,
+            learning_rate: 3e-4,
+            discount: 0.99,
+            gae_lambda: 0.95,
+            clip: 0.2,
+            epochs: 10,
+            minibatch: 2048,
+            iterations: None,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Layer {
+    weights: Tensor,
+    bias: Tensor,
+}
+
+/// Fully connected layers with tanh between them and nothing after the last.
+#[derive(Clone)]
+struct Mlp {
+    layers: Vec<Layer>,
+}
+
+impl Mlp {
+    /// Glorot-initialised layers, with the last one scaled by `output_scale` so that a policy can
+    /// start out close to zero.
+    fn new(sizes: &[usize], output_scale: f32) -> Self {
+        let mut rng = thread_rng();
+        let count = sizes.len() - 1;
+        let layers = sizes
+            .windows(2)
+            .enumerate()
+            .map(|(i, pair)| {
+                let (inputs, outputs) = (pair[0], pair[1]);
+                let limit = (6.0 / (inputs + outputs) as f32).sqrt();
+                let scale = if i + 1 == count { output_scale } else { 1.0 };
+                let weights = (0..inputs * outputs)
+                    .map(|_| rng.gen_range(-limit..limit) * scale)
+                    .collect();
+                Layer {
+                    weights: Tensor::new(inputs, outputs, weights),
+                    bias: Tensor::zeros(1, outputs),
+                }
+            })
+            .collect();
+        Self { layers }
+    }
+
+    fn forward(&self, input: &[f32]) -> Vec<f32> {
+        let mut x = input.to_vec();
+        for (i, layer) in self.layers.iter().enumerate() {
+            let w = &layer.weights;
+            x = (0..w.cols)
+                .map(|c| {
+                    let sum = layer.bias.data[c]
+                        + x.iter()
+                            .enumerate()
+                            .map(|(r, &v)| v * w.get(r, c))
+                            .sum::<f32>();
+                    if i + 1 < self.layers.len() {
+                        sum.tanh()
+                    } else {
+                        sum
+                    }
+                })
+                .collect();
+        }
+        x
+    }
+
+    /// Records the forward pass of a batch with one row per sample, given the weights and biases
+    /// of every layer in order as recorded on `tape`.
+    fn record(&self, tape: &mut Tape, parameters: &[Var], input: Var) -> Var {
+        let mut x = input;
+        for (i, pair) in parameters.chunks(2).enumerate() {
+            let product = tape.matmul(x, pair[0]);
+            x = tape.add_row(product, pair[1]);
+            if i + 1 < self.layers.len() {
+                x = tape.tanh(x);
+            }
+        }
+        x
+    }
+
+    fn tensors(&self) -> impl Iterator<Item = &Tensor> {
+        self.layers.iter().flat_map(|l| [&l.weights, &l.bias])
+    }
+
+    fn tensors_mut(&mut self) -> impl Iterator<Item = &mut Tensor> {
+        self.layers
+            .iter_mut()
+            .flat_map(|l| [&mut l.weights, &mut l.bias])
+    }
+}
+
+/// Samples the speed from a normal distribution around the output of the actor, with a learned
+/// standard deviation that doesn't depend on the observation.
+#[derive(Clone)]
+struct Policy {
+    actor: Mlp,
+    critic: Mlp,
+    log_std: Tensor,
+}
+
+impl Policy {
+    fn new(hidden: usize) -> Self {
+        let sizes = [OBSERVATIONS, hidden, hidden, 1];
+        Self {
+            actor: Mlp::new(&sizes, 0.01),
+            critic: Mlp::new(&sizes, 1.0),
+            log_std: Tensor::scalar(-0.5),
+        }
+    }
+
+    fn tensors(&self) -> Vec<&Tensor> {
+        self.actor
+            .tensors()
+            .chain(self.critic.tensors())
+            .chain([&self.log_std])
+            .collect()
+    }
+
+    fn tensors_mut(&mut self) -> Vec<&mut Tensor> {
+        self.actor
+            .tensors_mut()
+            .chain(self.critic.tensors_mut())
+            .chain([&mut self.log_std])
+            .collect()
+    }
+
+    /// Samples an action and returns it with its log probability.
+    fn act(&self, observation: &[f32], rng: &mut impl Rng) -> (f32, f32) {
+        let mean = self.actor.forward(observation)[0];
+        let log_std = self.log_std.data[0];
+        let action = mean + gaussian(rng, log_std.exp());
+        (action, log_probability(action, mean, log_std))
+    }
+
+    fn value(&self, observation: &[f32]) -> f32 {
+        self.critic.forward(observation)[0]
+    }
+}
+
+/// Log density of a normal distribution, without the constant term that cancels out of every
+/// use here.
+fn log_probability(action: f32, mean: f32, log_std: f32) -> f32 {
+    let z = (action - mean) / log_std.exp();
+    -0.5 * z * z - log_std
+}
+
+/// Everything an update needs to know about one step of an episode.
+#[derive(Clone)]
+struct Sample {
+    observation: [f32; OBSERVATIONS],
+    action: f32,
+    log_probability: f32,
+    advantage: f32,
+    target: f32,
+}
+
+/// Adam with bias correction, one moment pair per parameter tensor.
+struct Adam {
+    learning_rate: f32,
+    first: Vec<Vec<f32>>,
+    second: Vec<Vec<f32>>,
+    steps: i32,
+}
+
+impl Adam {
+    const BETA1: f32 = 0.9;
+    const BETA2: f32 = 0.999;
+    const EPSILON: f32 = 1e-8;
+
+    fn new(learning_rate: f32, parameters: &[&Tensor]) -> Self {
+        let zeros: Vec<Vec<f32>> = parameters.iter().map(|p| vec![0.0; p.data.len()]).collect();
+        Self {
+            learning_rate,
+            first: zeros.clone(),
+            second: zeros,
+            steps: 0,
+        }
+    }
+
+    /// Moves every parameter against its gradient.
+    fn step(&mut self, parameters: Vec<&mut Tensor>, gradients: &[&Tensor]) {
+        self.steps += 1;
+        let correction1 = 1.0 - Self::BETA1.powi(self.steps);
+        let correction2 = 1.0 - Self::BETA2.powi(self.steps);
+        let norm = gradients
+            .iter()
+            .flat_map(|g| &g.data)
+            .map(|g| g * g)
+            .sum::<f32>()
+            .sqrt();
+        let clip = (MAX_GRADIENT_NORM / norm.max(f32::EPSILON)).min(1.0);
+        for (i, (parameter, gradient)) in parameters.into_iter().zip(gradients).enumerate() {
+            let moments = self.first[i].iter_mut().zip(&mut self.second[i]);
+            for ((p, &g), (m, v)) in parameter.data.iter_mut().zip(&gradient.data).zip(moments) {
+                let g = g * clip;
+                *m = Self::BETA1 * *m + (1.0 - Self::BETA1) * g;
+                *v = Self::BETA2 * *v + (1.0 - Self::BETA2) * g * g;
+                *p -= self.learning_rate * (*m / correction1)
+                    / ((*v / correction2).sqrt() + Self::EPSILON);
+            }
+        }
+    }
+}
+
+/// Runs one episode with `policy` and returns its score and samples. Advantages are generalised
+/// advantage estimates over the value baseline, treating the end of the episode as terminal.
+fn rollout(policy: &Policy, env: &pendulum::EnvConfig, config: &RlConfig) -> (f32, Vec<Sample>) {
+    let mut rng = thread_rng();
+    let mut samples = Vec::new();
+    let mut values = Vec::new();
+    let rewards = pendulum::run_policy(env, |inputs| {
+        let observation = inputs.to_array();
+        let (action, log_probability) = policy.act(&observation, &mut rng);
+        values.push(policy.value(&observation));
+        samples.push(Sample {
+            observation,
+            action,
+            log_probability,
+            advantage: 0.0,
+            target: 0.0,
+        });
+        action
+    });
+
+    let lambda = match config.algorithm {
+        Algorithm::Reinforce => 1.0,
+        Algorithm::Ppo => config.gae_lambda,
+    };
+    estimate_advantages(&mut samples, &rewards, &values, config.discount, lambda);
+    (rewards.iter().sum(), samples)
+}
+
+/// Fills in the advantages and value targets of an episode's samples from its `rewards` and
+/// the critic's `values`.
+fn estimate_advantages(
+    samples: &mut [Sample],
+    rewards: &[f32],
+    values: &[f32],
+    discount: f32,
+    lambda: f32,
+) {
+    let mut advantage = 0.0;
+    let mut next_value = 0.0;
+    for (i, sample) in samples.iter_mut().enumerate().rev() {
+        let delta = rewards[i] + discount * next_value - values[i];
+        advantage = delta + discount * lambda * advantage;
+        sample.advantage = advantage;
+        sample.target = advantage + values[i];
+        next_value = values[i];
+    }
+}
+
+impl Ml {
+    /// Trains a policy with gradient-based reinforcement learning, reporting the scores of the
+    /// episodes of every update like the generations of `run_generations`. The policies aren't
+    /// agents, so nothing is sent to the viewer or saved.
+    pub fn run_rl(&mut self, config: &RlConfig) {
+        let mut policy = Policy::new(config.hidden);
+        let mut adam = Adam::new(config.learning_rate, &policy.tensors());
+        while self.keep_going(config.iterations) {
+            self.rl_iteration(&mut policy, &mut adam, config);
+        }
+    }
+
+    fn rl_iteration(&mut self, policy: &mut Policy, adam: &mut Adam, config: &RlConfig) {
+        use rayon::prelude::*;
+        let start = Instant::now();
+        let episodes: Vec<(f32, Vec<Sample>)> = (0..config.episodes)
+            .into_par_iter()
+            .map(|_| rollout(policy, &self.env, config))
+            .collect();
+        let evaluation_time = start.elapsed();
+        let scores: Vec<f32> = episodes.iter().map(|e| e.0).collect();
+        let mut samples: Vec<Sample> = episodes.into_iter().flat_map(|e| e.1).collect();
+        normalise_advantages(&mut samples);
+
+        let (epochs, minibatch) = match config.algorithm {
+            Algorithm::Reinforce => (1, samples.len()),
+            Algorithm::Ppo => (config.epochs, config.minibatch),
+        };
+        let mut rng = thread_rng();
+        for _ in 0..epochs {
+            samples.shuffle(&mut rng);
+            for batch in samples.chunks(minibatch) {
+                update(policy, adam, batch, config);
+            }
+        }
+
+        self.generation += 1;
+        let best = scores.iter().copied().fold(f32::MIN, f32::max);
+        self.record_stats(GenerationStats::from_scores(
+            self.generation,
+            &scores,
+            evaluation_time,
+        ));
+        if best > self.best_score {
+            self.best_score = best;
+            println!("New best score: {best}");
+        }
+    }
+}
+
+fn normalise_advantages(samples: &mut [Sample]) {
+    let count = samples.len() as f32;
+    let mean = samples.iter().map(|s| s.advantage).sum::<f32>() / count;
+    let variance = samples
+        .iter()
+        .map(|s| (s.advantage - mean).powi(2))
+        .sum::<f32>()
+        / count;
+    let std = variance.sqrt().max(1e-6);
+    for sample in samples {
+        sample.advantage = (sample.advantage - mean) / std;
+    }
+}
+
+/// One gradient step on the policy loss of `config.algorithm` plus half the squared error of the
+/// value baseline.
+fn update(policy: &mut Policy, adam: &mut Adam, batch: &[Sample], config: &RlConfig) {
+    let column = |f: fn(&Sample) -> f32| Tensor::column(batch.iter().map(f).collect());
+    let mut tape = Tape::new();
+    let parameters: Vec<Var> = policy
+        .tensors()
+        .into_iter()
+        .map(|t| tape.leaf(t.clone()))
+        .collect();
+    let actor_count = policy.actor.layers.len() * 2;
+    let critic_count = policy.critic.layers.len() * 2;
+    let actor = &parameters[..actor_count];
+    let critic = &parameters[actor_count..actor_count + critic_count];
+    let log_std = parameters[actor_count + critic_count];
+
+    let observations = tape.leaf(Tensor::new(
+        batch.len(),
+        OBSERVATIONS,
+        batch.iter().flat_map(|s| s.observation).collect(),
+    ));
+    let actions = tape.leaf(column(|s| s.action));
+    let advantages = tape.leaf(column(|s| s.advantage));
+
+    let mean = policy.actor.record(&mut tape, actor, observations);
+    let difference = tape.sub(actions, mean);
+    let negative_log_std = tape.scale(log_std, -1.0);
+    let inverse_std = tape.exp(negative_log_std);
+    let z = tape.mul_row(difference, inverse_std);
+    let z_squared = tape.mul(z, z);
+    let half = tape.scale(z_squared, -0.5);
+    let log_probabilities = tape.add_row(half, negative_log_std);
+    let objective = match config.algorithm {
+        Algorithm::Reinforce => tape.mul(log_probabilities, advantages),
+        Algorithm::Ppo => {
+            let old = tape.leaf(column(|s| s.log_probability));
+            let log_ratio = tape.sub(log_probabilities, old);
+            let ratio = tape.exp(log_ratio);
+            let unclipped = tape.mul(ratio, advantages);
+            let clipped_ratio = tape.clamp(ratio, 1.0 - config.clip, 1.0 + config.clip);
+            let clipped = tape.mul(clipped_ratio, advantages);
+            tape.min(unclipped, clipped)
+        }
+    };
+    let mean_objective = tape.mean(objective);
+    let policy_loss = tape.scale(mean_objective, -1.0);
+
+    let values = policy.critic.record(&mut tape, critic, observations);
+    let targets = tape.leaf(column(|s| s.target));
+    let error = tape.sub(values, targets);
+    let squared_error = tape.mul(error, error);
+    let mean_squared_error = tape.mean(squared_error);
+    let value_loss = tape.scale(mean_squared_error, 0.5);
+    let loss = tape.add(policy_loss, value_loss);
+
+    let gradients = tape.backward(loss);
+    let gradients: Vec<&Tensor> = parameters.iter().map(|&p| gradients.get(p)).collect();
+    adam.step(policy.tensors_mut(), &gradients);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undiscounted_monte_carlo_advantages_add_up_to_the_return() {
+        let rewards = [1.0, 0.0, 2.0, 0.5, 3.0];
+        let values = [0.3, -1.0, 2.5, 0.0, 1.0];
+        let mut samples = vec![
+            Sample {
+                observation: [0.0; OBSERVATIONS],
+                action: 0.0,
+                log_probability: 0.0,
+                advantage: 0.0,
+                target: 0.0,
+            };
+            rewards.len()
+        ];
+        estimate_advantages(&mut samples, &rewards, &values, 1.0, 1.0);
+        for (i, sample) in samples.iter().enumerate() {
+            let monte_carlo_return: f32 = rewards[i..].iter().sum();
+            assert!((sample.advantage + values[i] - monte_carlo_return).abs() < 1e-5);
+            assert_eq!(sample.target, sample.advantage + values[i]);
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use rand::distributions::{Uniform, WeightedError, WeightedIndex};
+use rand::prelude::*;
+use std::str::FromStr;
+
+/// How parents are picked from an evaluated population, by whatever weight the search ranks
+/// agents with: score, novelty and so on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Selection {
+    /// In proportion to the weights, uniformly if they are all zero.
+    Proportional,
+    /// Best of `size` agents drawn uniformly.
+    Tournament { size: usize },
+    /// In proportion to the position when sorted by weight, so only the order matters.
+    Rank,
+    /// Uniformly among the best `fraction` of the population.
+    Truncation { fraction: f32 },
+    /// In proportion to `exp(weight / temperature)`; low temperatures approach always picking
+    /// the best agent, high ones approach uniform sampling.
+    Boltzmann { temperature: f32 },
+    /// Proportional, but with evenly spaced pointers over the cumulative weights, so that every
+    /// agent is picked within one of its expected number of times.
+    StochasticUniversal,
+}
+
+impl Selection {
+    /// Indices of `count` parents. `weights` may be in any order.
+    pub fn parents(&self, weights: &[f32], count: usize, rng: &mut impl Rng) -> Vec<usize> {
+        if count == 0 {
+            return Vec::new();
+        }
+        match *self {
+            Self::Proportional => sample_weighted(weights, count, rng),
+            Self::Tournament { size } => (0..count)
+                .map(|_| {
+                    (0..size.max(1))
+                        .map(|_| rng.gen_range(0..weights.len()))
+                        .max_by(|&a, &b| weights[a].partial_cmp(&weights[b]).unwrap())
+                        .unwrap()
+                })
+                .collect(),
+            Self::Rank => {
+                let mut ranks = vec![0.0; weights.len()];
+                for (rank, i) in ascending(weights).into_iter().enumerate() {
+                    ranks[i] = (rank + 1) as f32;
+                }
+                sample_weighted(&ranks, count, rng)
+            }
+            Self::Truncation { fraction } => {
+                let order = ascending(weights);
+                let kept =
+                    ((fraction * weights.len() as f32).ceil() as usize).clamp(1, order.len());
+                let best = &order[order.len() - kept..];
+                (0..count).map(|_| *best.choose(rng).unwrap()).collect()
+            }
+            Self::Boltzmann { temperature } => {
+                let max = weights.iter().copied().fold(f32::MIN, f32::max);
+                let boltzmann: Vec<f32> = weights
+                    .iter()
+                    .map(|w| ((w - max) / temperature).exp())
+                    .collect();
+                sample_weighted(&boltzmann, count, rng)
+            }
+            Self::StochasticUniversal => {
+                let total: f32 = weights.iter().sum();
+                if total <= 0.0 {
+                    return sample_weighted(weights, count, rng);
+                }
+                let spacing = total / count as f32;
+                let mut pointer = rng.gen_range(0.0..spacing);
+                let mut cumulative = 0.0;
+                let mut parents = Vec::with_capacity(count);
+                for (i, &w) in weights.iter().enumerate() {
+                    cumulative += w;
+                    while pointer < cumulative && parents.len() < count {
+                        parents.push(i);
+                        pointer += spacing;
+                    }
+                }
+                // Rounding can leave the last pointer just past the end.
+                parents.resize(count, weights.len() - 1);
+                parents
+            }
+        }
+    }
+}
+
+/// `proportional`, `tournament[:size]`, `rank`, `truncation[:fraction]`,
+/// `boltzmann[:temperature]` or `sus`.
+impl FromStr for Selection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, parameter) = match s.split_once(':') {
+            Some((name, parameter)) => (name, Some(parameter)),
+            None => (s, None),
+        };
+        let parse = |default: f32| match parameter {
+            Some(p) => p
+                .parse::<f32>()
+                .map_err(|e| format!("{e}: `{p}`"))
+                .and_then(|x| {
+                    if x > 0.0 {
+                        Ok(x)
+                    } else {
+                        Err(format!("selection parameter must be positive: `{p}`"))
+                    }
+                }),
+            None => Ok(default),
+        };
+        match name {
+            "proportional" => Ok(Self::Proportional),
+            "tournament" => Ok(Self::Tournament {
+                size: parse(3.0)? as usize,
+            }),
+            "rank" => Ok(Self::Rank),
+            "truncation" => Ok(Self::Truncation {
+                fraction: parse(0.5)?,
+            }),
+            "boltzmann" => Ok(Self::Boltzmann {
+                temperature: parse(100.0)?,
+            }),
+            "sus" => Ok(Self::StochasticUniversal),
+            _ => Err(format!("unknown selection `{s}`")),
+        }
+    }
+}
+
+/// Selection together with how many of the best agents survive unchanged.
+#[derive(Clone, Debug)]
+pub struct SelectionConfig {
+    pub selection: Selection,
+    pub elites: usize,
+}
+
+impl Default for SelectionConfig {
+    fn default() -> Self {
+        Self {
+            selection: Selection::Proportional,
+            elites: 3,
+        }
+    }
+}
+
+fn sample_weighted(weights: &[f32], count: usize, rng: &mut impl Rng) -> Vec<usize> {
+    match WeightedIndex::new(weights) {
+        Ok(dist) => (0..count).map(|_| dist.sample(rng)).collect(),
+        Err(WeightedError::AllWeightsZero) => {
+            let dist = Uniform::new(0, weights.len());
+            (0..count).map(|_| dist.sample(rng)).collect()
+        }
+        Err(err) => panic!("Invalid selection weights: {err}"),
+    }
+}
+
+/// Indices of `weights` from the lowest weight to the highest.
+fn ascending(weights: &[f32]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..weights.len()).collect();
+    order.sort_by(|&a, &b| weights[a].partial_cmp(&weights[b]).unwrap());
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Selection; 6] = [
+        Selection::Proportional,
+        Selection::Tournament { size: 3 },
+        Selection::Rank,
+        Selection::Truncation { fraction: 0.5 },
+        Selection::Boltzmann { temperature: 1.0 },
+        Selection::StochasticUniversal,
+    ];
+
+    #[test]
+    fn parents_are_in_bounds() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let populations: [&[f32]; 4] = [
+            &[1.0],
+            &[0.0, 0.0, 0.0],
+            &[3.0, 0.5, 2.0, 0.0, 7.0],
+            &[0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9, 1.0],
+        ];
+        for selection in ALL {
+            for weights in populations {
+                for count in [0, 1, weights.len(), 3 * weights.len()] {
+                    let parents = selection.parents(weights, count, &mut rng);
+                    assert_eq!(parents.len(), count, "{selection:?}");
+                    assert!(parents.iter().all(|&i| i < weights.len()), "{selection:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stochastic_universal_picks_within_one_of_the_expected_count() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let weights = [1.0, 4.0, 0.5, 2.5, 2.0];
+        let total: f32 = weights.iter().sum();
+        for count in [1, 7, 10, 33] {
+            for _ in 0..100 {
+                let parents = Selection::StochasticUniversal.parents(&weights, count, &mut rng);
+                for (i, w) in weights.iter().enumerate() {
+                    let picked = parents.iter().filter(|&&p| p == i).count() as f32;
+                    let expected = w / total * count as f32;
+                    assert!(
+                        (picked - expected).abs() < 1.0 + 1e-4,
+                        "{picked} vs {expected}"
+                    );
+                }
+            }
+        }
+    }
+}
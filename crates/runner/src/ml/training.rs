@@ -0,0 +1,140 @@
+use super::selection::SelectionConfig;
+use super::{Champion, Ml};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::JoinHandle;
+
+/// What a `Training` handle can ask of the thread it started. Commands take effect between
+/// generations.
+#[derive(Debug)]
+pub enum Command {
+    Pause,
+    Resume,
+    /// Ends training, which cannot be resumed afterwards.
+    Stop,
+    /// Picks parents with a new configuration from the next generation on, in the searches for
+    /// which `Search::follows_selection` holds. The others ignore it.
+    Select(SelectionConfig),
+    /// Asks for a `Snapshot`, sent back on the given channel.
+    Snapshot(Sender<Snapshot>),
+}
+
+/// Progress of training at the moment a `Command::Snapshot` was handled.
+#[derive(Clone)]
+pub struct Snapshot {
+    pub generation: usize,
+    pub paused: bool,
+    /// Best agent so far, `None` before the first one that scored above zero.
+    pub best: Option<Champion>,
+}
+
+/// Training running on its own thread, controlled through `Command`s. Dropping the handle stops
+/// training and waits for the current generation to finish.
+pub struct Training {
+    commands: Sender<Command>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Training {
+    pub fn spawn(mut ml: Ml, train: impl FnOnce(&mut Ml) + Send + 'static) -> Self {
+        let (commands, control) = mpsc::channel();
+        ml.control = Some(control);
+        let thread = std::thread::spawn(move || train(&mut ml));
+        Self {
+            commands,
+            thread: Some(thread),
+        }
+    }
+
+    /// Sends `command`, doing nothing if training has already ended.
+    pub fn send(&self, command: Command) {
+        let _ = self.commands.send(command);
+    }
+
+    /// Asks for a `Snapshot`, which arrives on the returned channel once the training thread
+    /// gets to it, up to a generation later. The channel disconnects instead if training has
+    /// ended.
+    pub fn request_snapshot(&self) -> Receiver<Snapshot> {
+        let (tx, rx) = mpsc::channel();
+        self.send(Command::Snapshot(tx));
+        rx
+    }
+
+    /// Whether training has ended, because it was stopped or ran all its generations.
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().map_or(true, |t| t.is_finished())
+    }
+}
+
+impl Drop for Training {
+    fn drop(&mut self) {
+        self.send(Command::Stop);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                eprintln!("Training thread panicked");
+            }
+        }
+    }
+}
+
+impl Ml {
+    /// Handles pending commands, blocking for as long as training is paused, and tells whether
+    /// another generation should run given the `limit` on generations. Training without a
+    /// handle only stops at the limit or when its champions have nowhere to go.
+    pub(super) fn keep_going(&mut self, limit: Option<usize>) -> bool {
+        while let Some(control) = &self.control {
+            let command = if self.paused {
+                control.recv().ok()
+            } else {
+                match control.try_recv() {
+                    Ok(command) => Some(command),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => None,
+                }
+            };
+            match command {
+                Some(Command::Pause) => self.paused = true,
+                Some(Command::Resume) => self.paused = false,
+                Some(Command::Select(config)) => self.parent_selection = config,
+                Some(Command::Snapshot(reply)) => {
+                    let _ = reply.send(Snapshot {
+                        generation: self.generation,
+                        paused: self.paused,
+                        best: self.best.clone(),
+                    });
+                }
+                Some(Command::Stop) | None => {
+                    self.control = None;
+                    self.stopped = true;
+                }
+            }
+        }
+        !self.stopped && limit.map_or(true, |n| self.generation < n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::pendulum::EnvConfig;
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn paused_training_holds_still_and_stops_when_dropped() {
+        let (tx, champions) = mpsc::channel();
+        let training = Training::spawn(Ml::new(tx, EnvConfig::default()), |ml| {
+            ml.run_generations(None)
+        });
+        training.send(Command::Pause);
+        let paused = training.request_snapshot().recv().unwrap();
+        assert!(paused.paused);
+        std::thread::sleep(Duration::from_millis(200));
+        let later = training.request_snapshot().recv().unwrap();
+        assert!(later.paused);
+        assert_eq!(later.generation, paused.generation);
+        assert!(!training.is_finished());
+
+        drop(training);
+        // Only disconnects once the training thread has ended.
+        champions.iter().for_each(drop);
+    }
+}
@@ -7,31 +7,104 @@ pub struct Pendulum {
     cart_linacc: f32,
     bob_angvel: f32,
     bob_angle: f32,
+    cart_force: f32,
+    bob_force: Vec2,
+}
+
+/// Everything that determines how the pendulum evolves, apart from the transient external forces.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PendulumState {
+    pub cart_x: f32,
+    pub cart_linvel: f32,
+    pub cart_linacc: f32,
+    pub bob_angle: f32,
+    pub bob_angvel: f32,
+}
+
+impl PendulumState {
+    /// At rest with the bob hanging straight down.
+    pub fn hanging() -> Self {
+        Self::default()
+    }
+
+    /// At rest and perfectly balanced.
+    pub fn upright() -> Self {
+        Self {
+            bob_angle: std::f32::consts::PI,
+            ..Self::default()
+        }
+    }
 }
 
 const CART_MAX_SPEED: f32 = 1.0;
-const CART_ACC: f32 = 4.0;
-const CART_FRICTION: f32 = 2.0;
-const GRAVITY: f32 = -9.81;
-const RADIUS: f32 = 0.4;
+pub const CART_ACC: f32 = 4.0;
+pub const CART_FRICTION: f32 = 2.0;
+pub const GRAVITY: f32 = -9.81;
+pub const RADIUS: f32 = 0.4;
 const MAX_X: f32 = 0.5;
 const MIN_X: f32 = -0.5;
 
 impl Pendulum {
     pub fn new() -> Self {
-        Self {
+        Self::from_state(PendulumState::hanging())
+    }
+
+    pub fn from_state(state: PendulumState) -> Self {
+        let mut pendulum = Self {
             cart_x: 0.0,
             cart_linvel: 0.0,
             cart_linacc: 0.0,
             bob_angvel: 0.0,
             bob_angle: 0.0,
-        }
+            cart_force: 0.0,
+            bob_force: Vec2::ZERO,
+        };
+        pendulum.restore(state);
+        pendulum
     }
 
     pub fn reset(&mut self) {
         *self = Self::new();
     }
 
+    pub fn snapshot(&self) -> PendulumState {
+        PendulumState {
+            cart_x: self.cart_x,
+            cart_linvel: self.cart_linvel,
+            cart_linacc: self.cart_linacc,
+            bob_angle: self.bob_angle,
+            bob_angvel: self.bob_angvel,
+        }
+    }
+
+    /// Overwrites the state and discards any forces applied since the last update.
+    pub fn restore(&mut self, state: PendulumState) {
+        self.set_cart_x(state.cart_x);
+        self.set_cart_linvel(state.cart_linvel);
+        self.cart_linacc = state.cart_linacc;
+        self.set_bob_angle(state.bob_angle);
+        self.set_angvel(state.bob_angvel);
+        self.cart_force = 0.0;
+        self.bob_force = Vec2::ZERO;
+    }
+
+    /// Places the cart, clamped to the track.
+    pub fn set_cart_x(&mut self, cart_x: f32) {
+        self.cart_x = cart_x.clamp(MIN_X, MAX_X);
+    }
+
+    pub fn set_cart_linvel(&mut self, cart_linvel: f32) {
+        self.cart_linvel = cart_linvel.clamp(-CART_MAX_SPEED, CART_MAX_SPEED);
+    }
+
+    pub fn set_bob_angle(&mut self, bob_angle: f32) {
+        self.bob_angle = bob_angle;
+    }
+
+    pub fn set_angvel(&mut self, angvel: f32) {
+        self.bob_angvel = angvel;
+    }
+
     pub fn move_left(&mut self) {
         self.cart_linacc = -CART_ACC;
     }
@@ -44,12 +117,31 @@ impl Pendulum {
         self.cart_linacc = 0.0;
     }
 
+    pub fn accelerate(&mut self, acc: f32) {
+        self.cart_linacc = acc;
+    }
+
+    /// Applies an external force to the cart for the next update.
+    pub fn push_cart(&mut self, force: f32) {
+        self.cart_force += force;
+    }
+
+    /// Applies an external force to the bob for the next update.
+    pub fn push_bob(&mut self, force: Vec2) {
+        self.bob_force += force;
+    }
+
+    /// External forces on the cart and bob that the next update will apply.
+    pub fn pending_forces(&self) -> (f32, Vec2) {
+        (self.cart_force, self.bob_force)
+    }
+
     pub fn update(&mut self, delta: Duration) {
         let delta_secs = delta.as_secs_f32();
 
         // Cart velocity
         let old_v = self.cart_linvel;
-        self.cart_linvel = (old_v + self.cart_linacc * delta_secs)
+        self.cart_linvel = (old_v + (self.cart_linacc + self.cart_force) * delta_secs)
             .clamp(
                 (MIN_X - self.cart_x) / delta_secs,
                 (MAX_X - self.cart_x) / delta_secs,
@@ -61,6 +153,7 @@ impl Pendulum {
         // Angular velocity
         self.bob_angvel +=
             (dvel * self.bob_angle.cos() + GRAVITY * self.bob_angle.sin() * delta_secs) / RADIUS;
+        self.bob_angvel += self.bob_force.dot(self.bob_tangent()) * delta_secs / RADIUS;
         self.bob_angle += self.bob_angvel * delta_secs;
 
         // Friction
@@ -68,21 +161,84 @@ impl Pendulum {
         self.cart_linvel = (self.cart_linvel.abs() - CART_FRICTION * delta_secs)
             .max(0.0)
             .copysign(self.cart_linvel);
+
+        self.cart_force = 0.0;
+        self.bob_force = Vec2::ZERO;
+    }
+
+    /// Unit direction the bob moves in as the angle increases.
+    fn bob_tangent(&self) -> Vec2 {
+        let (sin, cos) = self.bob_angle.sin_cos();
+        vec2(-cos, sin)
     }
 
     pub fn cart_x(&self) -> f32 {
         self.cart_x
     }
 
+    pub fn cart_linvel(&self) -> f32 {
+        self.cart_linvel
+    }
+
+    pub fn cart_linacc(&self) -> f32 {
+        self.cart_linacc
+    }
+
     pub fn bob_pos(&self) -> Vec2 {
-        Vec2::X * self.cart_x - Vec2::from((self.bob_angle).sin_cos()) * RADIUS
+        Vec2::X * self.cart_x + bob_offset(self.bob_angle)
+    }
+
+    pub fn bob_vel(&self) -> Vec2 {
+        Vec2::X * self.cart_linvel + self.bob_tangent() * self.bob_angvel * RADIUS
     }
 
     pub fn bob_pos_normalized(&self) -> Vec2 {
         Vec2::X * self.cart_x - Vec2::from((self.bob_angle).sin_cos())
     }
 
+    pub fn bob_angle(&self) -> f32 {
+        self.bob_angle
+    }
+
     pub fn angvel(&self) -> f32 {
         self.bob_angvel
     }
 }
+
+/// Position of the bob relative to the cart for a given angle.
+pub fn bob_offset(angle: f32) -> Vec2 {
+    -Vec2::from(angle.sin_cos()) * RADIUS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELTA: Duration = Duration::from_millis(16);
+
+    #[test]
+    fn restored_snapshot_evolves_like_the_original() {
+        let mut original = Pendulum::from_state(PendulumState {
+            bob_angle: 2.0,
+            bob_angvel: -1.0,
+            ..PendulumState::hanging()
+        });
+        original.accelerate(1.5);
+        for _ in 0..10 {
+            original.update(DELTA);
+        }
+
+        let mut copy = Pendulum::new();
+        copy.push_bob(Vec2::X);
+        copy.restore(original.snapshot());
+        assert_eq!(copy.snapshot(), original.snapshot());
+        assert_eq!(copy.cart_linacc(), 1.5);
+        assert_eq!(copy.pending_forces(), (0.0, Vec2::ZERO));
+
+        for _ in 0..20 {
+            original.update(DELTA);
+            copy.update(DELTA);
+        }
+        assert_eq!(copy.snapshot(), original.snapshot());
+    }
+}
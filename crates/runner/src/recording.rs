@@ -0,0 +1,261 @@
+use crate::pendulum::{Pendulum, PendulumState};
+use glam::*;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+const HEADER: &str = "pendulum-recording 1";
+
+/// Inputs to a single `Pendulum::update` and the state it produced.
+#[derive(Clone, Copy, Debug)]
+pub struct Tick {
+    pub delta: Duration,
+    pub cart_linacc: f32,
+    pub cart_force: f32,
+    pub bob_force: Vec2,
+    pub result: PendulumState,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Event {
+    Tick(Tick),
+    /// The state was overwritten, e.g. by a reset or loading a save state.
+    Restore(PendulumState),
+}
+
+/// Everything that happened to a pendulum, sufficient to reproduce it exactly.
+#[derive(Clone, Debug)]
+pub struct Recording {
+    pub initial: PendulumState,
+    pub events: Vec<Event>,
+}
+
+/// The first tick at which a replay didn't reproduce the recorded state.
+#[derive(Debug)]
+pub struct Divergence {
+    pub tick: usize,
+    pub expected: PendulumState,
+    pub actual: PendulumState,
+}
+
+impl Recording {
+    pub fn new(initial: PendulumState) -> Self {
+        Self {
+            initial,
+            events: Vec::new(),
+        }
+    }
+
+    /// Records the inputs that are about to be applied by `pendulum.update(delta)`.
+    /// Call `finish_tick` once the update is done.
+    pub fn start_tick(&mut self, pendulum: &Pendulum, delta: Duration) {
+        let (cart_force, bob_force) = pendulum.pending_forces();
+        self.events.push(Event::Tick(Tick {
+            delta,
+            cart_linacc: pendulum.cart_linacc(),
+            cart_force,
+            bob_force,
+            result: PendulumState::default(),
+        }));
+    }
+
+    pub fn finish_tick(&mut self, pendulum: &Pendulum) {
+        if let Some(Event::Tick(tick)) = self.events.last_mut() {
+            tick.result = pendulum.snapshot();
+        }
+    }
+
+    pub fn restore(&mut self, state: PendulumState) {
+        self.events.push(Event::Restore(state));
+    }
+
+    pub fn ticks(&self) -> usize {
+        self.events
+            .iter()
+            .filter(|e| matches!(e, Event::Tick(_)))
+            .count()
+    }
+
+    /// Runs the recorded inputs through a fresh pendulum and checks every resulting state.
+    pub fn verify(&self) -> Result<(), Divergence> {
+        let mut pendulum = Pendulum::from_state(self.initial);
+        let mut index = 0;
+        for event in &self.events {
+            match event {
+                Event::Tick(tick) => {
+                    pendulum.accelerate(tick.cart_linacc);
+                    pendulum.push_cart(tick.cart_force);
+                    pendulum.push_bob(tick.bob_force);
+                    pendulum.update(tick.delta);
+                    let actual = pendulum.snapshot();
+                    if actual != tick.result {
+                        return Err(Divergence {
+                            tick: index,
+                            expected: tick.result,
+                            actual,
+                        });
+                    }
+                    index += 1;
+                }
+                Event::Restore(state) => pendulum.restore(*state),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = BufWriter::new(std::fs::File::create(path)?);
+        writeln!(file, "{HEADER}")?;
+        writeln!(file, "initial {}", format_state(&self.initial))?;
+        for event in &self.events {
+            match event {
+                Event::Tick(tick) => writeln!(
+                    file,
+                    "tick {} {} {} {} {} {}",
+                    tick.delta.as_nanos(),
+                    tick.cart_linacc,
+                    tick.cart_force,
+                    tick.bob_force.x,
+                    tick.bob_force.y,
+                    format_state(&tick.result)
+                )?,
+                Event::Restore(state) => writeln!(file, "restore {}", format_state(state))?,
+            }
+        }
+        file.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let file = io::BufReader::new(std::fs::File::open(path)?);
+        let mut lines = file.lines();
+        if lines.next().transpose()?.as_deref() != Some(HEADER) {
+            return Err(invalid_data("not a pendulum recording"));
+        }
+        let mut recording: Option<Self> = None;
+        for line in lines {
+            let line = line?;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match (fields.as_slice(), &mut recording) {
+                (["initial", state @ ..], None) => {
+                    recording = Some(Self::new(parse_state(state)?));
+                }
+                (["tick", delta, rest @ ..], Some(r)) if rest.len() == 9 => {
+                    let values = parse_floats(&rest[..4])?;
+                    r.events.push(Event::Tick(Tick {
+                        delta: Duration::from_nanos(
+                            delta.parse().map_err(|e| invalid_data(&format!("{e}")))?,
+                        ),
+                        cart_linacc: values[0],
+                        cart_force: values[1],
+                        bob_force: vec2(values[2], values[3]),
+                        result: parse_state(&rest[4..])?,
+                    }))
+                }
+                (["restore", state @ ..], Some(r)) => {
+                    r.events.push(Event::Restore(parse_state(state)?))
+                }
+                _ => return Err(invalid_data(&format!("unexpected line `{line}`"))),
+            }
+        }
+        recording.ok_or_else(|| invalid_data("missing initial state"))
+    }
+}
+
+fn format_state(state: &PendulumState) -> String {
+    let mut out = String::new();
+    for value in [
+        state.cart_x,
+        state.cart_linvel,
+        state.cart_linacc,
+        state.bob_angle,
+        state.bob_angvel,
+    ] {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        // `Display` for floats round-trips exactly, which keeps replays deterministic.
+        write!(out, "{value}").unwrap();
+    }
+    out
+}
+
+fn parse_floats(fields: &[&str]) -> io::Result<Vec<f32>> {
+    fields
+        .iter()
+        .map(|f| f.parse().map_err(|e| invalid_data(&format!("{e}: `{f}`"))))
+        .collect()
+}
+
+fn parse_state(fields: &[&str]) -> io::Result<PendulumState> {
+    if fields.len() != 5 {
+        return Err(invalid_data("a state has 5 values"));
+    }
+    let values = parse_floats(fields)?;
+    Ok(PendulumState {
+        cart_x: values[0],
+        cart_linvel: values[1],
+        cart_linacc: values[2],
+        bob_angle: values[3],
+        bob_angvel: values[4],
+    })
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A session with a command, drags and a restore in the middle, like the viewer records.
+    fn record_session() -> Recording {
+        let delta = Duration::from_nanos(1_000_000_000 / 60);
+        let mut pendulum = Pendulum::new();
+        let mut recording = Recording::new(pendulum.snapshot());
+        for step in 0..240 {
+            if step == 20 {
+                pendulum.move_right();
+            }
+            if step % 50 == 0 {
+                pendulum.push_bob(vec2(0.5, 0.2));
+                pendulum.push_cart(-1.5);
+            }
+            if step == 120 {
+                pendulum.restore(PendulumState::upright());
+                recording.restore(pendulum.snapshot());
+            }
+            recording.start_tick(&pendulum, delta);
+            pendulum.update(delta);
+            recording.finish_tick(&pendulum);
+        }
+        recording
+    }
+
+    #[test]
+    fn saved_recording_replays_exactly() {
+        let recording = record_session();
+        let path = std::env::temp_dir().join(format!("recording-{}.rec", std::process::id()));
+        recording.save(&path).unwrap();
+        let loaded = Recording::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.ticks(), 240);
+        assert!(loaded.verify().is_ok());
+    }
+
+    #[test]
+    fn tampered_recording_diverges_at_the_changed_tick() {
+        let mut recording = record_session();
+        let Event::Tick(tick) = &mut recording.events[30] else {
+            panic!("Expected a tick");
+        };
+        tick.cart_force += 1.0;
+        assert_eq!(recording.verify().unwrap_err().tick, 30);
+    }
+}
@@ -0,0 +1,181 @@
+use crate::pendulum::Pendulum;
+use rand::prelude::*;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Measurement {
+    pub cart_x: f32,
+    pub bob_angle: f32,
+    pub angvel: f32,
+}
+
+impl Measurement {
+    pub fn from_pendulum(pendulum: &Pendulum) -> Self {
+        Self {
+            cart_x: pendulum.cart_x(),
+            bob_angle: pendulum.bob_angle(),
+            angvel: pendulum.angvel(),
+        }
+    }
+}
+
+/// Error model of a single measured quantity.
+#[derive(Clone, Debug, Default)]
+pub struct ChannelConfig {
+    /// Standard deviation of the white noise added to every sample.
+    pub noise_std: f32,
+    /// Encoder step size, or zero for a continuous reading.
+    pub resolution: f32,
+    /// Standard deviation of the bias random walk after one second.
+    pub drift_std: f32,
+}
+
+impl ChannelConfig {
+    fn corrupt(&self, value: f32, bias: f32, rng: &mut impl Rng) -> f32 {
+        let value = value + bias + gaussian(rng, self.noise_std);
+        if self.resolution > 0.0 {
+            (value / self.resolution).round() * self.resolution
+        } else {
+            value
+        }
+    }
+
+    fn drift(&self, bias: &mut f32, delta: Duration, rng: &mut impl Rng) {
+        *bias += gaussian(rng, self.drift_std * delta.as_secs_f32().sqrt());
+    }
+}
+
+/// Observation pipeline between the true pendulum state and a controller.
+///
+/// The default configuration is a perfect, instantaneous sensor.
+#[derive(Clone, Debug, Default)]
+pub struct SensorConfig {
+    pub cart_x: ChannelConfig,
+    pub bob_angle: ChannelConfig,
+    pub angvel: ChannelConfig,
+    /// Time between samples, held in between. Zero samples on every tick.
+    pub sample_period: Duration,
+    /// Fixed delay before a sample becomes visible.
+    pub latency: Duration,
+    /// Upper bound of an additional uniformly distributed delay.
+    pub latency_jitter: Duration,
+}
+
+impl SensorConfig {
+    /// Roughly a cheap optical encoder on the cart and a 12 bit one on the pivot.
+    pub fn realistic() -> Self {
+        Self {
+            cart_x: ChannelConfig {
+                noise_std: 0.002,
+                resolution: 0.001,
+                drift_std: 0.0005,
+            },
+            bob_angle: ChannelConfig {
+                noise_std: 0.002,
+                resolution: std::f32::consts::TAU / 4096.0,
+                drift_std: 0.0005,
+            },
+            angvel: ChannelConfig {
+                noise_std: 0.05,
+                resolution: 0.0,
+                drift_std: 0.01,
+            },
+            sample_period: Duration::from_millis(10),
+            latency: Duration::from_millis(5),
+            latency_jitter: Duration::from_millis(5),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Sensors {
+    config: SensorConfig,
+    time: Duration,
+    next_sample: Duration,
+    bias: Measurement,
+    pending: VecDeque<(Duration, Measurement)>,
+    /// Latest released sample, `None` until the first one arrives.
+    output: Option<Measurement>,
+    /// Source of the noise, drift and jitter. Resets keep drawing from it.
+    rng: StdRng,
+}
+
+impl Sensors {
+    pub fn new(config: SensorConfig) -> Self {
+        Self::with_rng(config, StdRng::from_entropy())
+    }
+
+    /// Sensors whose errors are the same every time for the same `seed`.
+    pub fn seeded(config: SensorConfig, seed: u64) -> Self {
+        Self::with_rng(config, StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(config: SensorConfig, rng: StdRng) -> Self {
+        Self {
+            config,
+            time: Duration::ZERO,
+            next_sample: Duration::ZERO,
+            bias: Measurement::default(),
+            pending: VecDeque::new(),
+            output: None,
+            rng,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::with_rng(self.config.clone(), self.rng.clone());
+    }
+
+    /// Advances the sensor clock by `delta` and returns the latest visible measurement, or `None`
+    /// while the first sample is still on its way.
+    pub fn observe(&mut self, pendulum: &Pendulum, delta: Duration) -> Option<Measurement> {
+        let rng = &mut self.rng;
+        let config = &self.config;
+        self.time += delta;
+
+        config.cart_x.drift(&mut self.bias.cart_x, delta, rng);
+        config.bob_angle.drift(&mut self.bias.bob_angle, delta, rng);
+        config.angvel.drift(&mut self.bias.angvel, delta, rng);
+
+        if self.time >= self.next_sample {
+            let truth = Measurement::from_pendulum(pendulum);
+            let sample = Measurement {
+                cart_x: config.cart_x.corrupt(truth.cart_x, self.bias.cart_x, rng),
+                bob_angle: config
+                    .bob_angle
+                    .corrupt(truth.bob_angle, self.bias.bob_angle, rng),
+                angvel: config.angvel.corrupt(truth.angvel, self.bias.angvel, rng),
+            };
+            let jitter = config.latency_jitter.mul_f32(rng.gen());
+            // Samples arrive in order even when the jitter would reorder them.
+            let release = (self.time + config.latency + jitter)
+                .max(self.pending.back().map_or(Duration::ZERO, |p| p.0));
+            self.pending.push_back((release, sample));
+            self.next_sample = if config.sample_period.is_zero() {
+                self.time
+            } else {
+                (self.next_sample + config.sample_period).max(self.time)
+            };
+        }
+
+        while let Some(&(release, sample)) = self.pending.front() {
+            if release > self.time {
+                break;
+            }
+            self.output = Some(sample);
+            self.pending.pop_front();
+        }
+        self.output
+    }
+}
+
+/// Normally distributed sample using the Box-Muller transform.
+pub fn gaussian(rng: &mut impl Rng, std: f32) -> f32 {
+    if std == 0.0 {
+        return 0.0;
+    }
+    let u1: f32 = 1.0 - rng.gen::<f32>();
+    let u2: f32 = rng.gen();
+    std * (-2.0 * u1.ln()).sqrt() * (std::f32::consts::TAU * u2).cos()
+}
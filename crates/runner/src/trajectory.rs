@@ -0,0 +1,182 @@
+use crate::pendulum::{Pendulum, PendulumState};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"PTRJ";
+const VERSION: u32 = 1;
+const TYPE_F32: u8 = 0;
+
+/// Per-step record of a simulation, stored column by column.
+#[derive(Clone, Default)]
+pub struct Trajectory {
+    time: Vec<f32>,
+    cart_x: Vec<f32>,
+    cart_linvel: Vec<f32>,
+    bob_angle: Vec<f32>,
+    angvel: Vec<f32>,
+    action: Vec<f32>,
+    reward: Vec<f32>,
+}
+
+impl Trajectory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.time.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.time.is_empty()
+    }
+
+    /// Records the state after a step together with the action that led to it.
+    pub fn push(&mut self, time: f32, pendulum: &Pendulum, action: f32, reward: f32) {
+        self.time.push(time);
+        self.cart_x.push(pendulum.cart_x());
+        self.cart_linvel.push(pendulum.cart_linvel());
+        self.bob_angle.push(pendulum.bob_angle());
+        self.angvel.push(pendulum.angvel());
+        self.action.push(action);
+        self.reward.push(reward);
+    }
+
+    /// States after each step. The cart acceleration isn't recorded and is left at zero.
+    pub fn states(&self) -> impl Iterator<Item = PendulumState> + '_ {
+        (0..self.len()).map(|i| PendulumState {
+            cart_x: self.cart_x[i],
+            cart_linvel: self.cart_linvel[i],
+            cart_linacc: 0.0,
+            bob_angle: self.bob_angle[i],
+            bob_angvel: self.angvel[i],
+        })
+    }
+
+    fn columns(&self) -> [(&'static str, &[f32]); 7] {
+        [
+            ("time", &self.time),
+            ("cart_x", &self.cart_x),
+            ("cart_linvel", &self.cart_linvel),
+            ("bob_angle", &self.bob_angle),
+            ("angvel", &self.angvel),
+            ("action", &self.action),
+            ("reward", &self.reward),
+        ]
+    }
+
+    pub fn write_csv(&self, path: &Path) -> io::Result<()> {
+        let mut file = create(path)?;
+        let columns = self.columns();
+        let header: Vec<&str> = columns.iter().map(|c| c.0).collect();
+        writeln!(file, "{}", header.join(","))?;
+        for row in 0..self.len() {
+            for (i, (_, values)) in columns.iter().enumerate() {
+                if i > 0 {
+                    write!(file, ",")?;
+                }
+                write!(file, "{}", values[row])?;
+            }
+            writeln!(file)?;
+        }
+        file.flush()
+    }
+
+    /// Writes a compact binary file that is read one column at a time.
+    ///
+    /// All integers are little endian. The header is the magic `PTRJ`, a `u32` version, a `u32`
+    /// column count and a `u64` row count, followed by each column's `u16` name length, UTF-8
+    /// name and `u8` type tag (0 for `f32`). The columns' values follow back to back.
+    pub fn write_columnar(&self, path: &Path) -> io::Result<()> {
+        let mut file = create(path)?;
+        let columns = self.columns();
+        file.write_all(MAGIC)?;
+        file.write_all(&VERSION.to_le_bytes())?;
+        file.write_all(&(columns.len() as u32).to_le_bytes())?;
+        file.write_all(&(self.len() as u64).to_le_bytes())?;
+        for (name, _) in &columns {
+            file.write_all(&(name.len() as u16).to_le_bytes())?;
+            file.write_all(name.as_bytes())?;
+            file.write_all(&[TYPE_F32])?;
+        }
+        for (_, values) in &columns {
+            for value in values.iter() {
+                file.write_all(&value.to_le_bytes())?;
+            }
+        }
+        file.flush()
+    }
+
+    /// Writes `<stem>.csv` and `<stem>.ptrj`.
+    pub fn export(&self, stem: &Path) -> io::Result<()> {
+        self.write_csv(&stem.with_extension("csv"))?;
+        self.write_columnar(&stem.with_extension("ptrj"))
+    }
+}
+
+fn create(path: &Path) -> io::Result<BufWriter<std::fs::File>> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(BufWriter::new(std::fs::File::create(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Takes the next `N` bytes off the front of `bytes`.
+    fn take<const N: usize>(bytes: &mut &[u8]) -> [u8; N] {
+        let (head, rest) = bytes.split_at(N);
+        *bytes = rest;
+        head.try_into().unwrap()
+    }
+
+    #[test]
+    fn columnar_file_holds_the_csv_values() {
+        let delta = Duration::from_millis(20);
+        let mut pendulum = Pendulum::new();
+        let mut trajectory = Trajectory::new();
+        for step in 0..25 {
+            let action = if step < 10 { 1.0 } else { -0.5 };
+            pendulum.accelerate(action * 4.0);
+            pendulum.update(delta);
+            trajectory.push(step as f32 * 0.02, &pendulum, action, step as f32 / 7.0);
+        }
+        let stem = std::env::temp_dir().join(format!("trajectory-{}", std::process::id()));
+        trajectory.export(&stem).unwrap();
+        let csv = std::fs::read_to_string(stem.with_extension("csv"));
+        let columnar = std::fs::read(stem.with_extension("ptrj"));
+        std::fs::remove_file(stem.with_extension("csv")).unwrap();
+        std::fs::remove_file(stem.with_extension("ptrj")).unwrap();
+
+        let csv = csv.unwrap();
+        let mut lines = csv.lines();
+        let names: Vec<&str> = lines.next().unwrap().split(',').collect();
+        let rows: Vec<Vec<f32>> = lines
+            .map(|line| line.split(',').map(|v| v.parse().unwrap()).collect())
+            .collect();
+        assert_eq!(rows.len(), 25);
+
+        let columnar = columnar.unwrap();
+        let mut bytes = columnar.as_slice();
+        assert_eq!(&take::<4>(&mut bytes), MAGIC);
+        assert_eq!(u32::from_le_bytes(take(&mut bytes)), VERSION);
+        assert_eq!(u32::from_le_bytes(take(&mut bytes)) as usize, names.len());
+        assert_eq!(u64::from_le_bytes(take(&mut bytes)), 25);
+        for name in &names {
+            let len = u16::from_le_bytes(take(&mut bytes)) as usize;
+            let (name_bytes, rest) = bytes.split_at(len);
+            bytes = rest;
+            assert_eq!(std::str::from_utf8(name_bytes).unwrap(), *name);
+            assert_eq!(take::<1>(&mut bytes), [TYPE_F32]);
+        }
+        for column in 0..names.len() {
+            for row in &rows {
+                assert_eq!(f32::from_le_bytes(take(&mut bytes)), row[column]);
+            }
+        }
+        assert!(bytes.is_empty());
+    }
+}
@@ -0,0 +1,163 @@
+use shared::ShaderConstants;
+use spirv_std::glam::*;
+#[cfg_attr(not(target_arch = "spirv"), allow(unused_imports))]
+use spirv_std::num_traits::Float;
+
+/// Screen pixels per font cell.
+const SCALE: f32 = 3.0;
+const MARGIN: f32 = 12.0;
+/// Glyphs are 3x5 cells with a one cell gap on each side.
+const CHAR_WIDTH: f32 = 4.0;
+const LINE_HEIGHT: f32 = 7.0;
+const LABEL_CHARS: u32 = 5;
+const VALUE_CHARS: u32 = 10;
+const LINES: u32 = 7;
+
+const fn pack(label: &[u8; 4]) -> u32 {
+    (label[0] as u32) | (label[1] as u32) << 8 | (label[2] as u32) << 16 | (label[3] as u32) << 24
+}
+
+/// 3x5 bitmaps, three bits per row from the top, most significant bit on the left.
+fn glyph(c: u32) -> u32 {
+    match c {
+        0x30 => 0b111_101_101_101_111,
+        0x31 => 0b010_110_010_010_111,
+        0x32 => 0b111_001_111_100_111,
+        0x33 => 0b111_001_111_001_111,
+        0x34 => 0b101_101_111_001_001,
+        0x35 => 0b111_100_111_001_111,
+        0x36 => 0b111_100_111_101_111,
+        0x37 => 0b111_001_001_001_001,
+        0x38 => 0b111_101_111_101_111,
+        0x39 => 0b111_101_111_001_111,
+        0x2e => 0b000_000_000_000_010, // .
+        0x2d => 0b000_000_111_000_000, // -
+        0x41 => 0b010_101_111_101_101, // A
+        0x42 => 0b110_101_110_101_110, // B
+        0x43 => 0b011_100_100_100_011, // C
+        0x45 => 0b111_100_110_100_111, // E
+        0x47 => 0b011_100_101_101_011, // G
+        0x49 => 0b111_010_010_010_111, // I
+        0x4c => 0b100_100_100_100_111, // L
+        0x4d => 0b101_111_111_101_101, // M
+        0x4e => 0b110_101_101_101_101, // N
+        0x50 => 0b110_101_110_100_100, // P
+        0x52 => 0b110_101_110_101_101, // R
+        0x53 => 0b011_100_010_001_110, // S
+        0x54 => 0b111_010_010_010_010, // T
+        0x56 => 0b101_101_101_101_010, // V
+        _ => 0,
+    }
+}
+
+fn packed_char(packed: u32, col: u32) -> u32 {
+    if col < 4 {
+        (packed >> (8 * col)) & 0xff
+    } else {
+        0x20
+    }
+}
+
+fn label(line: u32) -> u32 {
+    match line {
+        0 => pack(b"CTRL"),
+        1 => pack(b"GEN "),
+        2 => pack(b"BEST"),
+        3 => pack(b"TIME"),
+        4 => pack(b"VEL "),
+        5 => pack(b"ANG "),
+        _ => pack(b"EP  "),
+    }
+}
+
+fn pow10(n: u32) -> u32 {
+    let mut result = 1;
+    let mut i = 0;
+    while i < n {
+        result *= 10;
+        i += 1;
+    }
+    result
+}
+
+/// Digits up to the one before the decimal point are always shown.
+fn digit_shown(n: u32, decimals: u32, digit: u32) -> bool {
+    digit <= decimals || (digit < 10 && n / pow10(digit) > 0)
+}
+
+/// Character `r` places from the right of `value` printed with `decimals` decimals.
+fn number_char(value: f32, decimals: u32, r: u32) -> u32 {
+    let n = (value.abs() * pow10(decimals) as f32).round() as u32;
+    if decimals > 0 && r == decimals {
+        return 0x2e;
+    }
+    let digit = if decimals > 0 && r > decimals {
+        r - 1
+    } else {
+        r
+    };
+    if digit_shown(n, decimals, digit) {
+        0x30 + (n / pow10(digit)) % 10
+    } else if value < 0.0 && digit > 0 && digit_shown(n, decimals, digit - 1) {
+        0x2d
+    } else {
+        0x20
+    }
+}
+
+fn value_char(line: u32, r: u32, constants: &ShaderConstants) -> u32 {
+    match line {
+        0 => {
+            let text = if constants.controller == 0 {
+                pack(b"LIVE")
+            } else {
+                pack(b"SAVE")
+            };
+            if r < 4 {
+                packed_char(text, 3 - r)
+            } else {
+                0x20
+            }
+        }
+        1 => number_char(constants.generation as f32, 0, r),
+        2 => number_char(constants.training_score, 1, r),
+        3 => number_char(constants.time, 2, r),
+        4 => number_char(constants.cart_linvel, 2, r),
+        5 => number_char(constants.pole_angle, 1, r),
+        _ => number_char(constants.episode_score, 1, r),
+    }
+}
+
+/// Coverage of the HUD text and its backdrop at pixel `p`, measured from the top left corner.
+pub fn hud(p: Vec2, constants: &ShaderConstants) -> Vec2 {
+    let cell = (p - Vec2::splat(MARGIN)) / SCALE;
+    let size = vec2(
+        (LABEL_CHARS + VALUE_CHARS) as f32 * CHAR_WIDTH,
+        LINES as f32 * LINE_HEIGHT,
+    );
+    let backdrop = if cell.cmpge(Vec2::splat(-2.0)).all() && cell.cmplt(size + 1.0).all() {
+        1.0
+    } else {
+        0.0
+    };
+    if cell.x < 0.0 || cell.y < 0.0 || cell.x >= size.x || cell.y >= size.y {
+        return vec2(0.0, backdrop);
+    }
+
+    let line = (cell.y / LINE_HEIGHT) as u32;
+    let col = (cell.x / CHAR_WIDTH) as u32;
+    let c = if col < LABEL_CHARS {
+        packed_char(label(line), col)
+    } else {
+        value_char(line, LABEL_CHARS + VALUE_CHARS - 1 - col, constants)
+    };
+
+    let x = (cell.x - col as f32 * CHAR_WIDTH) as u32;
+    let y = (cell.y - line as f32 * LINE_HEIGHT) as u32;
+    let text = if x < 3 && y < 5 && (glyph(c) >> (14 - (y * 3 + x))) & 1 == 1 {
+        1.0
+    } else {
+        0.0
+    };
+    vec2(text, backdrop)
+}
@@ -1,11 +1,13 @@
 #![cfg_attr(target_arch = "spirv", no_std)]
 
-use shared::ShaderConstants;
+use shared::{NetworkView, ShaderConstants};
 use spirv_std::glam::*;
 #[cfg_attr(not(target_arch = "spirv"), allow(unused_imports))]
 use spirv_std::num_traits::Float;
 use spirv_std::spirv;
 
+mod hud;
+mod network;
 mod sdf;
 
 fn smoothstep(a: f32, b: f32, x: f32) -> f32 {
@@ -37,6 +39,7 @@ fn cart_col(p: Vec2) -> Vec3 {
 pub fn main_fs(
     #[spirv(frag_coord)] frag_coord: Vec4,
     #[spirv(push_constant)] constants: &ShaderConstants,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] network: &NetworkView,
     output: &mut Vec4,
 ) {
     let uv = (vec2(frag_coord.x, -frag_coord.y)
@@ -61,6 +64,10 @@ pub fn main_fs(
         col += bob_col;
     }
 
+    let hud = hud::hud(frag_coord.xy(), constants);
+    col = col.lerp(Vec3::ZERO, 0.6 * hud.y).max(Vec3::splat(hud.x));
+    col = network::network(frag_coord.xy(), constants.width as f32, network, col);
+
     *output = col.powf(2.2).extend(1.0);
 }
 
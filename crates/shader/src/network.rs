@@ -0,0 +1,79 @@
+use crate::{sdf, smoothstep};
+use shared::{NetworkView, MAX_NET_EDGES, MAX_NET_NODES, NET_NODE_INPUT, NET_NODE_OUTPUT};
+use spirv_std::glam::*;
+#[cfg_attr(not(target_arch = "spirv"), allow(unused_imports))]
+use spirv_std::num_traits::Float;
+
+/// Panel size in screen pixels.
+const PANEL: Vec2 = vec2(320.0, 220.0);
+const MARGIN: f32 = 12.0;
+/// Space between the panel border and the outermost nodes.
+const PADDING: f32 = 18.0;
+const NODE_RADIUS: f32 = 7.0;
+const MAX_EDGE_WIDTH: f32 = 3.0;
+
+const POSITIVE: Vec3 = vec3(0.2, 0.6, 1.0);
+const NEGATIVE: Vec3 = vec3(1.0, 0.35, 0.15);
+
+fn node_pos(net: &NetworkView, origin: Vec2, i: u32) -> Vec2 {
+    let node = net.nodes[i as usize];
+    origin + Vec2::splat(PADDING) + vec2(node.x, node.y) * (PANEL - 2.0 * PADDING)
+}
+
+fn kind_col(kind: u32) -> Vec3 {
+    if kind == NET_NODE_INPUT {
+        vec3(0.3, 1.0, 0.3)
+    } else if kind == NET_NODE_OUTPUT {
+        vec3(1.0, 0.3, 0.3)
+    } else {
+        Vec3::splat(0.7)
+    }
+}
+
+/// Draws the network panel in the top right corner over `col` at pixel `p`, measured from the
+/// top left corner. Edges are blue when positive and orange when negative, wider the stronger
+/// they are and faded when recurrent, and nodes are filled from black to white with their last
+/// activation.
+pub fn network(p: Vec2, width: f32, net: &NetworkView, col: Vec3) -> Vec3 {
+    let origin = vec2(width - MARGIN - PANEL.x, MARGIN);
+    let local = p - origin;
+    if local.x < 0.0 || local.y < 0.0 || local.x >= PANEL.x || local.y >= PANEL.y {
+        return col;
+    }
+    let mut col = col.lerp(Vec3::ZERO, 0.6);
+
+    let edge_count = net.edge_count.min(MAX_NET_EDGES as u32);
+    let node_count = net.node_count.min(MAX_NET_NODES as u32);
+    let mut i = 0;
+    while i < edge_count {
+        let edge = net.edges[i as usize];
+        if edge.source < node_count && edge.target < node_count {
+            let a = node_pos(net, origin, edge.source);
+            let b = node_pos(net, origin, edge.target);
+            let half_width = 0.5 * (0.5 + edge.weight.abs()).min(MAX_EDGE_WIDTH);
+            let coverage = smoothstep(1.0, 0.0, sdf::capsule(p, a, b, half_width));
+            let edge_col = if edge.weight >= 0.0 {
+                POSITIVE
+            } else {
+                NEGATIVE
+            };
+            let opacity = if edge.recurrent != 0 { 0.5 } else { 1.0 };
+            col = col.lerp(edge_col, opacity * coverage);
+        }
+        i += 1;
+    }
+
+    let mut i = 0;
+    while i < node_count {
+        let node = net.nodes[i as usize];
+        let d = sdf::disk(p - node_pos(net, origin, i), NODE_RADIUS);
+        let fill = Vec3::splat(0.5 + 0.5 * node.activation.tanh());
+        col = col.lerp(fill, smoothstep(1.0, 0.0, d));
+        col = col.lerp(
+            kind_col(node.kind),
+            smoothstep(1.0, 0.0, (d + 1.0).abs() - 1.0),
+        );
+        i += 1;
+    }
+    col
+}
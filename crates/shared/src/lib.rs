@@ -17,4 +17,53 @@ pub struct ShaderConstants {
     pub cart_x: f32,
     pub bob_x: f32,
     pub bob_y: f32,
+
+    /// 0 while following training, 1 while a restored agent is pinned.
+    pub controller: u32,
+    pub generation: u32,
+    pub training_score: f32,
+    pub time: f32,
+    pub cart_linvel: f32,
+    /// Degrees from upright.
+    pub pole_angle: f32,
+    pub episode_score: f32,
+}
+
+pub const MAX_NET_NODES: usize = 64;
+pub const MAX_NET_EDGES: usize = 256;
+
+pub const NET_NODE_INPUT: u32 = 0;
+pub const NET_NODE_HIDDEN: u32 = 1;
+pub const NET_NODE_OUTPUT: u32 = 2;
+
+#[derive(Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+pub struct NetNode {
+    /// Position in the unit square of the network panel, y pointing down.
+    pub x: f32,
+    pub y: f32,
+    pub activation: f32,
+    /// One of the `NET_NODE_*` constants.
+    pub kind: u32,
+}
+
+#[derive(Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+pub struct NetEdge {
+    pub source: u32,
+    pub target: u32,
+    pub weight: f32,
+    /// 1 for edges carrying the previous tick's activation.
+    pub recurrent: u32,
+}
+
+/// Network of the active agent, bound as a storage buffer.
+#[derive(Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+pub struct NetworkView {
+    pub node_count: u32,
+    pub edge_count: u32,
+    pub _padding: [u32; 2],
+    pub nodes: [NetNode; MAX_NET_NODES],
+    pub edges: [NetEdge; MAX_NET_EDGES],
 }